@@ -7,6 +7,21 @@
 log Testing without default features
 cargo test --no-default-features
 
+# --- Test with pure-Rust BPS decoder ----------------------------------------
+
+log Testing with pure-Rust BPS decoder
+cargo test --features pure-rust
+
+# --- Test with parallel BPS delta creation ----------------------------------
+
+log Testing with parallel BPS delta creation
+cargo test --features parallel
+
+# --- Test with memory-mapped files ------------------------------------------
+
+log Testing with memory-mapped files
+cargo test --features mmap
+
 # --- Test with coverage -----------------------------------------------------
 
 log Measuring code coverage
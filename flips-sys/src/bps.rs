@@ -29,6 +29,15 @@ pub enum bpserror {
     bps_shut_up_gcc,
 }
 
+/// A callback reporting the progress of a delta patch creation.
+///
+/// It receives the `userdata` given to `bps_create_delta_inmem`, and the
+/// amount of work done so far out of the `total`. Return `false` to cancel
+/// the patch creation.
+pub type bps_progress = Option<
+    unsafe extern "C" fn(userdata: *mut libc::c_void, done: libc::size_t, total: libc::size_t) -> bool
+>;
+
 #[link(name="bps")]
 extern "C" {
 
@@ -57,13 +66,18 @@ extern "C" {
         patch: *mut mem
     ) -> bpserror;
 
+    /// Creates a BPS patch that converts `source` to `target` and stores it to `patch`.
+    ///
+    /// If `progress` is not `None`, it is called regularly with `userdata`
+    /// and how far the creation went. If it returns `false`, the creation
+    /// is stopped and `bps_canceled` is returned.
     pub fn bps_create_delta_inmem(
         source: mem,
         target: mem,
         metadata: mem,
         patch: *mut mem,
-        progress: *const libc::c_void, // FIXME
-        userdata: *const libc::c_void,
+        progress: bps_progress,
+        userdata: *mut libc::c_void,
         moremem: bool,
     ) -> bpserror;
 }
@@ -108,7 +122,7 @@ mod tests {
         unsafe {
             // create patch
             let mut mem_patch = mem::default();
-            let result = super::bps_create_delta_inmem(source.to_mem(), target.to_mem(), mem::default(), &mut mem_patch as *mut mem, core::ptr::null(), core::ptr::null(), false);
+            let result = super::bps_create_delta_inmem(source.to_mem(), target.to_mem(), mem::default(), &mut mem_patch as *mut mem, None, core::ptr::null_mut(), false);
             assert_eq!(result, bpserror::bps_ok, "could not create patch");
 
             // apply patch
@@ -31,10 +31,16 @@ pub enum ipserror {
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub struct ipsstudy {
-    error: ipserror,
-    outlen_min: libc::c_uint,
-    outlen_max: libc::c_uint,
-    outlen_min_mem: libc::c_uint,
+    /// The error found in the patch, if any.
+    pub error: ipserror,
+    /// The minimum length of the output.
+    pub outlen_min: libc::c_uint,
+    /// The maximum length of the output, or `0xFFFFFFFF` if the patch
+    /// does not truncate its output.
+    pub outlen_max: libc::c_uint,
+    /// The length of the buffer needed to write all the records, which
+    /// may be larger than the output if the patch truncates it.
+    pub outlen_min_mem: libc::c_uint,
 }
 
 impl Default for ipsstudy {
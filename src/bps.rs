@@ -4,6 +4,51 @@ use crate::Result;
 use crate::Error;
 use crate::FlipsMemory;
 
+mod actions;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod compose;
+#[cfg(any(feature = "std", feature = "pure-rust"))]
+mod decoder;
+mod format;
+#[cfg(feature = "std")]
+mod invert;
+#[cfg(feature = "std")]
+mod metadata;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod stats;
+#[cfg(feature = "std")]
+mod stream;
+mod verify;
+#[cfg(feature = "std")]
+mod writer;
+
+pub use self::actions::BpsAction;
+pub use self::actions::BpsActions;
+#[cfg(feature = "std")]
+pub use self::builder::BpsAttempt;
+#[cfg(feature = "std")]
+pub use self::builder::BpsBuilder;
+#[cfg(feature = "std")]
+pub use self::builder::BpsOutcome;
+#[cfg(feature = "std")]
+pub use self::builder::BpsReport;
+#[cfg(feature = "std")]
+pub use self::builder::BpsStrategy;
+#[cfg(feature = "std")]
+pub use self::metadata::BpsManifest;
+#[cfg(feature = "std")]
+pub use self::metadata::BpsMetadata;
+pub use self::stats::BpsActionStats;
+pub use self::stats::BpsStats;
+#[cfg(feature = "std")]
+pub use self::writer::BpsWriter;
+
+#[cfg(feature = "pure-rust")]
+use self::decoder::apply as apply_raw;
+
 // ---------------------------------------------------------------------------
 
 /// A patch in the BPS format.
@@ -21,34 +66,297 @@ impl<B: AsRef<[u8]>> BpsPatch<B> {
         Self { buffer }
     }
 
+    /// Read the information stored in the patch header and footer.
+    ///
+    /// This does not require the source the patch applies to, so it can
+    /// be used to check which source is expected before applying it. The
+    /// checksums are read from the patch footer, but not verified.
+    ///
+    /// # Error
+    /// If the patch is too short or does not start with the BPS magic
+    /// bytes, this method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn info(&self) -> Result<BpsInfo<'_>> {
+        self::format::read_info(self.buffer.as_ref()).map(|(info, _)| info)
+    }
+
+    /// Check the patch is complete and not corrupted.
+    ///
+    /// This reads the header and all the actions of the patch, and compares
+    /// the checksum of the patch with the one stored in its footer. It does
+    /// not require the source the patch applies to, so it can be used to
+    /// reject damaged patches early.
+    ///
+    /// # Error
+    /// - [`Error::BadMagic`] if the patch is not a BPS patch.
+    /// - [`Error::Truncated`] if the patch ends before all its actions.
+    /// - [`Error::BadChecksum`] if the patch content was altered.
+    /// - [`Error::Invalid`] if the patch is intact but malformed.
+    ///
+    /// [`Error::BadMagic`]: ./enum.Error.html#variant.BadMagic
+    /// [`Error::Truncated`]: ./enum.Error.html#variant.Truncated
+    /// [`Error::BadChecksum`]: ./enum.Error.html#variant.BadChecksum
+    /// [`Error::Invalid`]: ./enum.Error.html#variant.Invalid
+    pub fn verify(&self) -> Result<()> {
+        self::verify::verify(self.buffer.as_ref())
+    }
+
+    /// Compute statistics about the content of the patch.
+    ///
+    /// This reads all the actions of the patch, to count the bytes written
+    /// by each kind of action. The checksums are not verified.
+    ///
+    /// # Error
+    /// If the patch can't be read, this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn stats(&self) -> Result<BpsStats> {
+        self::stats::stats(self.buffer.as_ref())
+    }
+
+    /// Iterate over the actions of the patch.
+    ///
+    /// The actions are checked against the sizes declared in the patch
+    /// header while they are read, but the checksums are not verified.
+    ///
+    /// # Error
+    /// If the patch header can't be read, this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid). Errors in
+    /// the actions themselves are reported by the iterator.
+    pub fn actions(&self) -> Result<BpsActions<'_>> {
+        let (info, reader) = self::format::read_info(self.buffer.as_ref())?;
+        Ok(BpsActions::new(&info, reader))
+    }
+
     /// Apply the patch to a source.
     #[must_use]
     pub fn apply<S: AsRef<[u8]>>(&self, source: S) -> Result<BpsOutput> {
+        match apply_raw(self.buffer.as_ref(), source.as_ref(), false)? {
+            (output, None) => Ok(output),
+            (_, Some(error)) => Err(error),
+        }
+    }
+
+    /// Apply the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io). Other errors are
+    /// reported like in [`apply`](#method.apply).
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+    #[cfg(feature = "mmap")]
+    pub unsafe fn apply_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<BpsOutput> {
+        self.apply(crate::MappedFile::open(path)?)
+    }
+
+    /// Apply the patch from a source stream to a target stream.
+    ///
+    /// Unlike [`apply`](#method.apply), neither the source nor the output
+    /// are loaded in memory: data is copied between the streams in chunks
+    /// of bounded size, and the checksums are computed on the fly. This makes
+    /// it possible to patch files larger than the available memory.
+    ///
+    /// The target is read back when the patch copies data from an earlier
+    /// position in the output, so it must implement `Read` as well as `Write`,
+    /// like a [`File`] opened in read-write mode. The output is written from
+    /// the start of the target, which is never truncated: the target must be
+    /// empty, or at most as long as the output.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # let bytes = include_bytes!("../tests/data/patch1to2.bps");
+    /// let patch = flips::BpsPatch::new(&bytes[..]);
+    /// let source = std::fs::File::open("source.iso").unwrap();
+    /// let target = std::fs::OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .create(true)
+    ///     .truncate(true)
+    ///     .open("target.iso")
+    ///     .unwrap();
+    /// patch.apply_stream(source, target).expect("could not apply patch");
+    /// ```
+    ///
+    /// # Error
+    /// Errors are reported like in [`apply`](#method.apply). Since the target
+    /// is written as the patch is applied, it may contain a partial output if
+    /// an error occurs. I/O errors are reported as [`Error::Io`]. If the
+    /// target is longer than the output, the bytes past the output are left
+    /// untouched, and this method will return [`Error::Io`] with the
+    /// [`InvalidInput`] kind once the output is written.
+    ///
+    /// [`File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    /// [`InvalidInput`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidInput
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn apply_stream<R, W>(&self, source: R, target: W) -> Result<()>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Read + std::io::Write + std::io::Seek,
+    {
+        self::stream::apply(self.buffer.as_ref(), source, target)
+    }
+
+    /// Compose this patch with a patch applying to its output.
+    ///
+    /// If this patch converts *A* to *B*, and `next` converts *B* to *C*,
+    /// then the returned patch converts *A* to *C* directly. The patches are
+    /// composed action by action, so neither *A*, *B* or *C* are needed.
+    /// The metadata of `next` is used for the returned patch.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// let v1_to_v2 = flips::BpsPatch::new(std::fs::read("v1_to_v2.bps").unwrap());
+    /// let v2_to_v3 = flips::BpsPatch::new(std::fs::read("v2_to_v3.bps").unwrap());
+    /// let v1_to_v3 = v1_to_v2.compose(&v2_to_v3).expect("could not compose patches");
+    /// ```
+    ///
+    /// # Error
+    /// If the source of `next` is not the output of this patch, according
+    /// to the sizes and checksums stored in the patches, this method will
+    /// return [`Error::NotThis`](./enum.Error.html#variant.NotThis). If any
+    /// of the patches is invalid or corrupted, it will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn compose<N: AsRef<[u8]>>(&self, next: &BpsPatch<N>) -> Result<BpsPatch<Vec<u8>>> {
+        self::compose::compose(self.buffer.as_ref(), next.buffer.as_ref())
+    }
+
+    /// Create the inverse of the patch, using the source it applies to.
+    ///
+    /// The returned patch converts the output of this patch back into
+    /// `source`. It is built from the actions of this patch rather than
+    /// with a new delta search, which makes it much cheaper to create than
+    /// with a [`BpsDeltaBuilder`]: regions of the output copied from the
+    /// source are copied back, and only the rest of the source is stored in
+    /// the inverse patch. The metadata of this patch is kept as is.
+    ///
+    /// # Error
+    /// Errors are reported like in [`apply`](#method.apply) if the patch
+    /// can't be applied to `source`.
+    ///
+    /// [`BpsDeltaBuilder`]: ./struct.BpsDeltaBuilder.html
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn invert<S: AsRef<[u8]>>(&self, source: S) -> Result<BpsPatch<Vec<u8>>> {
+        self::invert::invert(self.buffer.as_ref(), source.as_ref())
+    }
+
+    /// Apply the patch to a source, even if the checksums do not match.
+    ///
+    /// Unlike [`apply`](#method.apply), a source or an output which does not
+    /// match the checksums stored in the patch will not cause an error.
+    /// Instead, the output is returned along with a [`BpsWarning`] telling
+    /// which checksums did not match, if any. This can be used to apply a
+    /// patch to a slightly different revision of its source.
+    ///
+    /// # Error
+    /// Errors that prevent the creation of an output, such as an invalid
+    /// patch, or an action reading past the end of the source, are still
+    /// reported like in [`apply`](#method.apply).
+    ///
+    /// [`BpsWarning`]: ./struct.BpsWarning.html
+    pub fn apply_lenient<S: AsRef<[u8]>>(&self, source: S) -> Result<(BpsOutput, Option<BpsWarning>)> {
         let slice_p = self.buffer.as_ref();
         let slice_s = source.as_ref();
-        let mut mem_m = flips_sys::mem::default();
-        let mut mem_o = flips_sys::mem::default();
-
-        let result = unsafe {
-            let mem_i = flips_sys::mem::new(slice_s.as_ptr() as *mut _, slice_s.len());
-            let mem_p = flips_sys::mem::new(slice_p.as_ptr() as *mut _, slice_p.len());
-            flips_sys::bps::bps_apply(mem_p, mem_i, &mut mem_o as *mut _, &mut mem_m as *mut _, false)
+        let info = self.info()?;
+        let (output, _) = apply_raw(slice_p, slice_s, true)?;
+        let warning = BpsWarning {
+            source_mismatch: self::format::crc32(slice_s) != info.source_crc32,
+            target_mismatch: self::format::crc32(output.as_ref()) != info.target_crc32,
         };
-
-        match Error::from_bps(result) {
-            None if mem_m.ptr.is_null() => Ok(BpsOutput::from(FlipsMemory::new(mem_o))),
-            None => Ok(BpsOutput::with_metadata(FlipsMemory::new(mem_o), FlipsMemory::new(mem_m))),
-            Some(error) => Err(error),
+        if warning.source_mismatch || warning.target_mismatch {
+            Ok((output, Some(warning)))
+        } else {
+            Ok((output, None))
         }
     }
 }
 
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl BpsPatch<crate::MappedFile> {
+    /// Load a BPS patch from the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        crate::MappedFile::open(path).map(Self::new)
+    }
+}
+
 impl<B: AsRef<[u8]>> AsRef<[u8]> for BpsPatch<B> {
     fn as_ref(&self) -> &[u8] {
         self.buffer.as_ref()
     }
 }
 
+/// Apply a BPS patch with `libbps`.
+///
+/// If an error is returned along with an output, the output was created
+/// despite the error because `accept_wrong_input` was `true`.
+#[cfg(not(feature = "pure-rust"))]
+fn apply_raw(patch: &[u8], source: &[u8], accept_wrong_input: bool) -> Result<(BpsOutput, Option<Error>)> {
+    let mut mem_m = flips_sys::mem::default();
+    let mut mem_o = flips_sys::mem::default();
+
+    let result = unsafe {
+        let mem_i = flips_sys::mem::new(source.as_ptr() as *mut _, source.len());
+        let mem_p = flips_sys::mem::new(patch.as_ptr() as *mut _, patch.len());
+        flips_sys::bps::bps_apply(mem_p, mem_i, &mut mem_o as *mut _, &mut mem_m as *mut _, accept_wrong_input)
+    };
+
+    match Error::from_bps(result) {
+        Some(error) if mem_o.ptr.is_null() => Err(error),
+        error if mem_m.ptr.is_null() => Ok((BpsOutput::from(FlipsMemory::new(mem_o)), error)),
+        error => {
+            let output = BpsOutput::with_metadata(FlipsMemory::new(mem_o), FlipsMemory::new(mem_m));
+            Ok((output, error))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// The checksum mismatches found by [`BpsPatch::apply_lenient`].
+///
+/// [`BpsPatch::apply_lenient`]: ./struct.BpsPatch.html#method.apply_lenient
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BpsWarning {
+    /// The source checksum does not match the one stored in the patch.
+    pub source_mismatch: bool,
+    /// The output checksum does not match the one stored in the patch.
+    pub target_mismatch: bool,
+}
+
+// ---------------------------------------------------------------------------
+
+/// The information stored in the header and footer of a BPS patch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BpsInfo<'a> {
+    /// The size of the source the patch applies to.
+    pub source_size: u64,
+    /// The size of the target created by the patch.
+    pub target_size: u64,
+    /// The metadata embedded in the patch, possibly empty.
+    pub metadata: &'a [u8],
+    /// The CRC32 checksum of the source the patch applies to.
+    pub source_crc32: u32,
+    /// The CRC32 checksum of the target created by the patch.
+    pub target_crc32: u32,
+    /// The CRC32 checksum of the patch itself.
+    pub patch_crc32: u32,
+}
+
 // ---------------------------------------------------------------------------
 
 /// The output created by the application of a BPS patch.
@@ -66,6 +374,18 @@ impl BpsOutput {
             metadata: Some(metadata),
         }
     }
+
+    /// Get the metadata embedded in the patch, if any.
+    pub fn metadata(&self) -> Option<&[u8]> {
+        self.metadata.as_ref().map(|m| m.as_bytes())
+    }
+
+    /// Parse the metadata embedded in the patch, if any.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn parse_metadata(&self) -> Option<BpsMetadata> {
+        self.metadata().map(BpsMetadata::parse)
+    }
 }
 
 impl From<FlipsMemory> for BpsOutput {
@@ -182,6 +502,40 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>, M: AsRef<[u8]>> BpsLinearBuilder<S, T, M> {
     }
 }
 
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<T: AsRef<[u8]>, M: AsRef<[u8]>> BpsLinearBuilder<crate::MappedFile, T, M> {
+    /// Set the source of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn source_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.source(crate::MappedFile::open(path)?))
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<S: AsRef<[u8]>, M: AsRef<[u8]>> BpsLinearBuilder<S, crate::MappedFile, M> {
+    /// Set the target of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn target_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.target(crate::MappedFile::open(path)?))
+    }
+}
+
 // --
 
 /// A builder to create a BPS patch.
@@ -203,6 +557,8 @@ pub struct BpsDeltaBuilder<S: AsRef<[u8]>, T: AsRef<[u8]>, M: AsRef<[u8]> = &'st
     target: Option<T>,
     metadata: Option<M>,
     moremem: bool,
+    #[cfg(feature = "parallel")]
+    threads: Option<usize>,
 }
 
 impl<S: AsRef<[u8]>, T: AsRef<[u8]>> BpsDeltaBuilder<S, T, &'static [u8]> {
@@ -213,6 +569,8 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> BpsDeltaBuilder<S, T, &'static [u8]> {
             target: None,
             metadata: None,
             moremem: false,
+            #[cfg(feature = "parallel")]
+            threads: None,
         }
     }
 
@@ -223,6 +581,8 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> BpsDeltaBuilder<S, T, &'static [u8]> {
             target: self.target.take(),
             metadata: buffer.into(),
             moremem: self.moremem,
+            #[cfg(feature = "parallel")]
+            threads: self.threads,
         }
     }
 }
@@ -250,42 +610,218 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>, M: AsRef<[u8]>> BpsDeltaBuilder<S, T, M> {
         self
     }
 
+    /// Set the maximum number of threads used to build the patch.
+    ///
+    /// With more than one thread, the target is split in as many chunks as
+    /// there are threads, and each chunk is diffed against the region of the
+    /// source around it, extending by the size of a chunk on both sides.
+    /// Matches cannot cross the boundaries between chunks, nor reach source
+    /// data outside of that region, so the patch is larger than a patch built
+    /// on a single thread, especially when data moved over long distances.
+    /// Chunks are at least 1 MiB large, so small targets are never split.
+    ///
+    /// Use `1` to build the patch on the current thread (the default), or
+    /// `0` to let `rayon` pick the number of threads.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "parallel")))]
+    #[cfg(feature = "parallel")]
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = Some(threads);
+        self
+    }
+
     #[must_use]
     /// Build an BPS patch from `source` to `target` with `metadata` if any.
     ///
+    /// With the `parallel` feature, the patch can be built on several
+    /// threads at the cost of a larger patch, see [`threads`](#method.threads).
+    ///
     /// # Error
     /// If either `source` or `target` was not given, this method will
     /// return [`Error::Canceled`](./enum.Error.html#variant.Canceled).
     pub fn build(&mut self) -> Result<BpsPatch<FlipsMemory>> {
+        self.create(None, core::ptr::null_mut())
+    }
+
+    /// Build an BPS patch while reporting the progress of the creation.
+    ///
+    /// `progress` is called regularly with the amount of work done so far,
+    /// and the total amount of work to do. Return `false` from the closure
+    /// to stop the creation, in which case this method will return
+    /// [`Error::Canceled`](./enum.Error.html#variant.Canceled).
+    ///
+    /// The patch is always built on the current thread, since `libbps` reports
+    /// the progress of a single diff: the number of threads set with
+    /// [`threads`](#method.threads) is ignored.
+    ///
+    /// # Example
+    /// ```rust
+    /// let patch = flips::BpsDeltaBuilder::new()
+    ///     .source(&b"some source bytes"[..])
+    ///     .target(&b"some target bytes"[..])
+    ///     .build_with_progress(|done, total| {
+    ///         println!("{}/{}", done, total);
+    ///         true
+    ///     })
+    ///     .expect("could not create patch");
+    /// ```
+    ///
+    /// # Panics
+    /// The closure is called from `libbps`, which a panic cannot unwind
+    /// through. With the `std` feature, a panic inside the closure cancels
+    /// the creation, and is resumed once `libbps` returns. Without it, the
+    /// panic will abort the process.
+    pub fn build_with_progress<F>(&mut self, progress: F) -> Result<BpsPatch<FlipsMemory>>
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let mut progress = Progress {
+            callback: progress,
+            #[cfg(feature = "std")]
+            panic: None,
+        };
+        let userdata = &mut progress as *mut Progress<F> as *mut libc::c_void;
+        let result = self.create(Some(progress_trampoline::<F>), userdata);
+        #[cfg(feature = "std")]
+        {
+            if let Some(payload) = progress.panic {
+                std::panic::resume_unwind(payload);
+            }
+        }
+        result
+    }
+
+    fn create(
+        &mut self,
+        progress: flips_sys::bps::bps_progress,
+        userdata: *mut libc::c_void,
+    ) -> Result<BpsPatch<FlipsMemory>> {
         if self.source.is_none() || self.target.is_none() {
             return Err(Error::Canceled);
         }
 
         let (source, target) = (self.source.take().unwrap(), self.target.take().unwrap());
+        let metadata = self.metadata.take();
         let (slice_s, slice_t) = (source.as_ref(), target.as_ref());
-        let mut mem_patch = flips_sys::mem::default();
+        let slice_m = metadata.as_ref().map(AsRef::as_ref);
+
+        #[cfg(feature = "parallel")]
+        {
+            if let (None, Some(threads)) = (progress, self.threads) {
+                if threads != 1 {
+                    return parallel::create_delta(slice_s, slice_t, slice_m, self.moremem, threads)
+                        .map(BpsPatch::new);
+                }
+            }
+        }
 
-        let result = unsafe {
-            let mem_metadata = match self.metadata.take() {
-                Some(m) => flips_sys::mem::new(m.as_ref().as_ptr() as *mut _, m.as_ref().len()),
-                None => flips_sys::mem::default(),
-            };
-            let mem_s = flips_sys::mem::new(slice_s.as_ptr() as *mut _, slice_s.len());
-            let mem_t = flips_sys::mem::new(slice_t.as_ptr() as *mut _, slice_t.len());
-            flips_sys::bps::bps_create_delta_inmem(
-                mem_s,
-                mem_t,
-                mem_metadata,
-                &mut mem_patch as *mut _,
-                core::ptr::null(),
-                core::ptr::null(),
-                self.moremem,
-            )
+        create_delta(slice_s, slice_t, slice_m, progress, userdata, self.moremem).map(BpsPatch::new)
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<T: AsRef<[u8]>, M: AsRef<[u8]>> BpsDeltaBuilder<crate::MappedFile, T, M> {
+    /// Set the source of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn source_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.source(crate::MappedFile::open(path)?))
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<S: AsRef<[u8]>, M: AsRef<[u8]>> BpsDeltaBuilder<S, crate::MappedFile, M> {
+    /// Set the target of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn target_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.target(crate::MappedFile::open(path)?))
+    }
+}
+
+/// Create a delta patch from `source` to `target` with `libbps`.
+fn create_delta(
+    source: &[u8],
+    target: &[u8],
+    metadata: Option<&[u8]>,
+    progress: flips_sys::bps::bps_progress,
+    userdata: *mut libc::c_void,
+    moremem: bool,
+) -> Result<FlipsMemory> {
+    let mut mem_patch = flips_sys::mem::default();
+
+    let result = unsafe {
+        let mem_metadata = match metadata {
+            Some(m) => flips_sys::mem::new(m.as_ptr() as *mut _, m.len()),
+            None => flips_sys::mem::default(),
         };
+        let mem_s = flips_sys::mem::new(source.as_ptr() as *mut _, source.len());
+        let mem_t = flips_sys::mem::new(target.as_ptr() as *mut _, target.len());
+        flips_sys::bps::bps_create_delta_inmem(
+            mem_s,
+            mem_t,
+            mem_metadata,
+            &mut mem_patch as *mut _,
+            progress,
+            userdata,
+            moremem,
+        )
+    };
+
+    match Error::from_bps(result) {
+        None => Ok(FlipsMemory::new(mem_patch)),
+        Some(error) => Err(error),
+    }
+}
 
-        match Error::from_bps(result) {
-            None => Ok(BpsPatch::new(FlipsMemory::new(mem_patch))),
-            Some(error) => Err(error),
+/// The progress closure given to `libbps`, with the panic it raised, if any.
+struct Progress<F> {
+    callback: F,
+    #[cfg(feature = "std")]
+    panic: Option<Box<dyn std::any::Any + Send>>,
+}
+
+/// Forward a progress report from `libbps` to a Rust closure.
+unsafe extern "C" fn progress_trampoline<F>(
+    userdata: *mut libc::c_void,
+    done: libc::size_t,
+    total: libc::size_t,
+) -> bool
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    let progress = &mut *(userdata as *mut Progress<F>);
+    #[cfg(feature = "std")]
+    {
+        // NB: a panic cannot unwind through `libbps`, so it is caught here
+        //     to cancel the creation, and resumed by `build_with_progress`.
+        if progress.panic.is_some() {
+            return false;
         }
+        let callback = &mut progress.callback;
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(done, total))) {
+            Ok(proceed) => proceed,
+            Err(payload) => {
+                progress.panic = Some(payload);
+                false
+            }
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        (progress.callback)(done, total)
     }
 }
@@ -0,0 +1,188 @@
+use core::convert::TryFrom;
+use core::iter::FusedIterator;
+
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::format::Reader;
+use super::BpsInfo;
+
+// ---------------------------------------------------------------------------
+
+/// A single action of a BPS patch.
+///
+/// Each action writes `len` bytes to the output, starting at the output
+/// position it is yielded with by [`BpsActions`](./struct.BpsActions.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BpsAction<'a> {
+    /// Copy bytes from the source, at the same position as in the output.
+    SourceRead {
+        /// The number of bytes to copy.
+        len: u64,
+    },
+    /// Copy bytes stored in the patch itself.
+    TargetRead {
+        /// The bytes to copy.
+        bytes: &'a [u8],
+    },
+    /// Copy bytes from an arbitrary position in the source.
+    SourceCopy {
+        /// The position of the bytes to copy in the source.
+        offset: u64,
+        /// The number of bytes to copy.
+        len: u64,
+    },
+    /// Copy bytes from an earlier position in the output.
+    ///
+    /// The copied bytes may overlap with the bytes being written, in which
+    /// case they must be copied one at a time to reproduce a pattern.
+    TargetCopy {
+        /// The position of the bytes to copy in the output.
+        offset: u64,
+        /// The number of bytes to copy.
+        len: u64,
+    },
+}
+
+impl<'a> BpsAction<'a> {
+    /// Get the number of bytes written to the output by this action.
+    pub fn len(&self) -> u64 {
+        match *self {
+            BpsAction::SourceRead { len } => len,
+            BpsAction::TargetRead { bytes } => bytes.len() as u64,
+            BpsAction::SourceCopy { len, .. } => len,
+            BpsAction::TargetCopy { len, .. } => len,
+        }
+    }
+
+    /// Check whether this action writes no bytes to the output.
+    ///
+    /// Actions read from a valid patch always write at least one byte.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// An iterator over the actions of a BPS patch.
+///
+/// Each item is an action along with the position in the output where it
+/// starts writing. The iterator stops after the first error.
+///
+/// # Example
+/// ```rust
+/// # let bytes = include_bytes!("../../tests/data/patch1to2.bps");
+/// let patch = flips::BpsPatch::new(&bytes[..]);
+/// for item in patch.actions().expect("invalid header") {
+///     let (position, action) = item.expect("invalid action");
+///     println!("{:08x}: {:?}", position, action);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct BpsActions<'a> {
+    reader: Reader<'a>,
+    source_size: u64,
+    target_size: u64,
+    position: u64,
+    source_offset: u64,
+    target_offset: u64,
+    finished: bool,
+}
+
+impl<'a> BpsActions<'a> {
+    /// Create an iterator over the actions of a patch from its header.
+    pub(crate) fn new(info: &BpsInfo<'a>, reader: Reader<'a>) -> Self {
+        Self {
+            reader,
+            source_size: info.source_size,
+            target_size: info.target_size,
+            position: 0,
+            source_offset: 0,
+            target_offset: 0,
+            finished: false,
+        }
+    }
+
+    /// Check whether all the bytes of the patch were consumed.
+    ///
+    /// After an error, this tells whether the patch ended before the whole
+    /// output could be written, which is a sign of truncation.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.reader.is_empty()
+    }
+
+    /// Read the next action, or `None` if the whole output was written.
+    fn read(&mut self) -> Result<Option<(u64, BpsAction<'a>)>> {
+        if self.reader.is_empty() {
+            return match self.position == self.target_size {
+                true => Ok(None),
+                false => Err(Error::Invalid),
+            };
+        }
+
+        let data = self.reader.number()?;
+        let len = (data >> 2) + 1;
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.target_size)
+            .ok_or(Error::Invalid)?;
+
+        let action = match data & 3 {
+            0 => {
+                if end > self.source_size {
+                    return Err(Error::Invalid);
+                }
+                BpsAction::SourceRead { len }
+            }
+            1 => {
+                let len = usize::try_from(len).map_err(|_| Error::Invalid)?;
+                BpsAction::TargetRead { bytes: self.reader.bytes(len)? }
+            }
+            2 => {
+                let offset = format::relative(self.source_offset, self.reader.number()?)?;
+                self.source_offset = offset
+                    .checked_add(len)
+                    .filter(|&end| end <= self.source_size)
+                    .ok_or(Error::Invalid)?;
+                BpsAction::SourceCopy { offset, len }
+            }
+            _ => {
+                let offset = format::relative(self.target_offset, self.reader.number()?)?;
+                if offset >= self.position {
+                    return Err(Error::Invalid);
+                }
+                self.target_offset = offset + len;
+                BpsAction::TargetCopy { offset, len }
+            }
+        };
+
+        let position = self.position;
+        self.position = end;
+        Ok(Some((position, action)))
+    }
+}
+
+impl<'a> Iterator for BpsActions<'a> {
+    type Item = Result<(u64, BpsAction<'a>)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.read() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(error) => {
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl<'a> FusedIterator for BpsActions<'a> {}
@@ -0,0 +1,347 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Error;
+use crate::FlipsMemory;
+#[cfg(feature = "mmap")]
+use crate::MappedFile;
+use crate::Result;
+
+use super::BpsDeltaBuilder;
+use super::BpsLinearBuilder;
+use super::BpsPatch;
+
+/// A strategy to create a BPS patch.
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BpsStrategy {
+    /// Create the patch with a [`BpsLinearBuilder`](./struct.BpsLinearBuilder.html).
+    Linear,
+    /// Create the patch with a [`BpsDeltaBuilder`](./struct.BpsDeltaBuilder.html).
+    Delta,
+    /// Create the patch with a [`BpsDeltaBuilder`](./struct.BpsDeltaBuilder.html)
+    /// allowed to use more memory.
+    DeltaMoreMemory,
+}
+
+impl BpsStrategy {
+    /// Estimate the memory used by the strategy for the given buffer sizes.
+    ///
+    /// The estimates of the delta strategies are the figures given in
+    /// `libbps.h`, which documents the delta creator as using about 5 times
+    /// the combined size of the source and the target, and about 9 times
+    /// when allowed to use more memory. The linear strategy only allocates
+    /// the patch, which is about as large as the target in the worst case.
+    /// These are documented figures rather than measurements, so the actual
+    /// memory usage may differ slightly.
+    pub fn memory_estimate(&self, source_size: usize, target_size: usize) -> usize {
+        let total = source_size.saturating_add(target_size);
+        match self {
+            BpsStrategy::Linear => target_size,
+            BpsStrategy::Delta => total.saturating_mul(5),
+            BpsStrategy::DeltaMoreMemory => total.saturating_mul(9),
+        }
+    }
+}
+
+/// The outcome of a strategy run by a [`BpsBuilder`].
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq)]
+pub enum BpsOutcome {
+    /// The strategy created a patch of the given size.
+    Built(usize),
+    /// The strategy failed to create a patch.
+    Failed(Error),
+    /// The strategy was not run, because it would exceed the memory budget.
+    OverMemory,
+    /// The strategy was not run or was stopped, because it would exceed
+    /// the time budget.
+    OverTime,
+}
+
+/// The report of a single strategy run by a [`BpsBuilder`].
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BpsAttempt {
+    /// The strategy that was tried.
+    pub strategy: BpsStrategy,
+    /// The outcome of the strategy.
+    pub outcome: BpsOutcome,
+    /// The time spent running the strategy.
+    pub duration: Duration,
+}
+
+/// The report of the strategies run by a [`BpsBuilder`].
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BpsReport {
+    /// The strategy that created the smallest patch.
+    pub winner: BpsStrategy,
+    /// The attempts of every strategy, in the order they were tried.
+    pub attempts: Vec<BpsAttempt>,
+}
+
+/// A builder to create the smallest BPS patch among several strategies.
+///
+/// The linear and delta strategies are tried in turn, and the smallest of
+/// the patches they create is returned, along with a [`BpsReport`] of the
+/// outcome of each strategy. Strategies can be limited with a time and a
+/// memory budget.
+///
+/// # Example
+/// ```rust
+/// let (patch, report) = flips::BpsBuilder::new()
+///     .source(&b"some source bytes"[..])
+///     .target(&b"some target bytes"[..])
+///     .build()
+///     .expect("could not create patch");
+/// assert_eq!(report.attempts.len(), 2);
+/// ```
+///
+/// [`BpsReport`]: ./struct.BpsReport.html
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, Default)]
+pub struct BpsBuilder<S: AsRef<[u8]>, T: AsRef<[u8]>, M: AsRef<[u8]> = &'static [u8]> {
+    source: Option<S>,
+    target: Option<T>,
+    metadata: Option<M>,
+    moremem: bool,
+    time_budget: Option<Duration>,
+    memory_budget: Option<usize>,
+    #[cfg(feature = "parallel")]
+    threads: Option<usize>,
+}
+
+impl<S: AsRef<[u8]>, T: AsRef<[u8]>> BpsBuilder<S, T, &'static [u8]> {
+    /// Create a new builder for an BPS patch.
+    pub fn new() -> Self {
+        Self {
+            source: None,
+            target: None,
+            metadata: None,
+            moremem: false,
+            time_budget: None,
+            memory_budget: None,
+            #[cfg(feature = "parallel")]
+            threads: None,
+        }
+    }
+
+    /// Set the metadata buffer for the patch, if any.
+    pub fn metadata<M: AsRef<[u8]>, B: Into<Option<M>>>(&mut self, buffer: B) -> BpsBuilder<S, T, M> {
+        BpsBuilder {
+            source: self.source.take(),
+            target: self.target.take(),
+            metadata: buffer.into(),
+            moremem: self.moremem,
+            time_budget: self.time_budget,
+            memory_budget: self.memory_budget,
+            #[cfg(feature = "parallel")]
+            threads: self.threads,
+        }
+    }
+}
+
+impl<S: AsRef<[u8]>, T: AsRef<[u8]>, M: AsRef<[u8]>> BpsBuilder<S, T, M> {
+    /// Set the source buffer for the patch.
+    pub fn source(&mut self, source: S) -> &mut Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Set the target buffer for the patch.
+    pub fn target(&mut self, target: T) -> &mut Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Also try the delta strategy with more memory.
+    ///
+    /// See [`BpsDeltaBuilder::more_memory`](./struct.BpsDeltaBuilder.html#method.more_memory).
+    pub fn more_memory(&mut self, moremem: bool) -> &mut Self {
+        self.moremem = moremem;
+        self
+    }
+
+    /// Set the total time allowed to create the patch, if any.
+    ///
+    /// Strategies are not started once the budget is spent, and the delta
+    /// strategies are stopped when they run past it. The linear strategy
+    /// is fast enough that it always runs to completion once started.
+    ///
+    /// The delta strategies are stopped through the progress reports of
+    /// `libbps`, so with a time budget they always run on the current thread,
+    /// ignoring the number of threads set with [`threads`](#method.threads).
+    pub fn time_budget<D: Into<Option<Duration>>>(&mut self, budget: D) -> &mut Self {
+        self.time_budget = budget.into();
+        self
+    }
+
+    /// Set the memory allowed to create the patch, in bytes, if any.
+    ///
+    /// Strategies whose [estimated memory usage] is above the budget are
+    /// not run.
+    ///
+    /// [estimated memory usage]: ./enum.BpsStrategy.html#method.memory_estimate
+    pub fn memory_budget<B: Into<Option<usize>>>(&mut self, budget: B) -> &mut Self {
+        self.memory_budget = budget.into();
+        self
+    }
+
+    /// Set the maximum number of threads used by the delta strategies.
+    ///
+    /// See [`BpsDeltaBuilder::threads`](./struct.BpsDeltaBuilder.html#method.threads)
+    /// for the cost of building a patch on several threads. This setting is
+    /// ignored when a [time budget](#method.time_budget) is set.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "parallel")))]
+    #[cfg(feature = "parallel")]
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Build the smallest BPS patch from `source` to `target` with `metadata` if any.
+    ///
+    /// # Error
+    /// If either `source` or `target` was not given, this method will
+    /// return [`Error::Canceled`](./enum.Error.html#variant.Canceled). If
+    /// no strategy could create a patch, this method returns the error of
+    /// the first failed strategy, or [`Error::OutOfMem`] if all strategies
+    /// were over the memory budget, or [`Error::Canceled`] if they were
+    /// over the time budget.
+    ///
+    /// [`Error::OutOfMem`]: ./enum.Error.html#variant.OutOfMem
+    /// [`Error::Canceled`]: ./enum.Error.html#variant.Canceled
+    pub fn build(&mut self) -> Result<(BpsPatch<FlipsMemory>, BpsReport)> {
+        if self.source.is_none() || self.target.is_none() {
+            return Err(Error::Canceled);
+        }
+
+        let (source, target) = (self.source.take().unwrap(), self.target.take().unwrap());
+        let metadata = self.metadata.take();
+        let (slice_s, slice_t) = (source.as_ref(), target.as_ref());
+        let slice_m = metadata.as_ref().map(AsRef::as_ref);
+        if slice_s == slice_t {
+            return Err(Error::Identical);
+        }
+
+        let mut strategies = vec![BpsStrategy::Linear, BpsStrategy::Delta];
+        if self.moremem {
+            strategies.push(BpsStrategy::DeltaMoreMemory);
+        }
+
+        let deadline = self.time_budget.map(|budget| Instant::now() + budget);
+        let mut best: Option<(BpsStrategy, BpsPatch<FlipsMemory>)> = None;
+        let mut attempts = Vec::with_capacity(strategies.len());
+        for strategy in strategies {
+            let start = Instant::now();
+            let estimate = strategy.memory_estimate(slice_s.len(), slice_t.len());
+            let outcome = if self.memory_budget.is_some_and(|budget| estimate > budget) {
+                BpsOutcome::OverMemory
+            } else if deadline.is_some_and(|deadline| start >= deadline) {
+                BpsOutcome::OverTime
+            } else {
+                match self.run(strategy, slice_s, slice_t, slice_m, deadline) {
+                    Ok(patch) => {
+                        let size = patch.as_ref().len();
+                        if best.as_ref().is_none_or(|(_, b)| size < b.as_ref().len()) {
+                            best = Some((strategy, patch));
+                        }
+                        BpsOutcome::Built(size)
+                    }
+                    Err(Error::Canceled) => BpsOutcome::OverTime,
+                    Err(error) => BpsOutcome::Failed(error),
+                }
+            };
+            attempts.push(BpsAttempt {
+                strategy,
+                outcome,
+                duration: start.elapsed(),
+            });
+        }
+
+        match best {
+            Some((winner, patch)) => Ok((patch, BpsReport { winner, attempts })),
+            None => Err(Self::failure(&attempts)),
+        }
+    }
+
+    /// Create a patch with the given strategy, stopping at `deadline` if any.
+    fn run(
+        &self,
+        strategy: BpsStrategy,
+        source: &[u8],
+        target: &[u8],
+        metadata: Option<&[u8]>,
+        deadline: Option<Instant>,
+    ) -> Result<BpsPatch<FlipsMemory>> {
+        if strategy == BpsStrategy::Linear {
+            return BpsLinearBuilder::new().source(source).target(target).metadata::<&[u8], _>(metadata).build();
+        }
+
+        let mut builder = BpsDeltaBuilder::new()
+            .source(source)
+            .target(target)
+            .more_memory(strategy == BpsStrategy::DeltaMoreMemory)
+            .metadata::<&[u8], _>(metadata);
+        #[cfg(feature = "parallel")]
+        {
+            if let Some(threads) = self.threads {
+                builder.threads(threads);
+            }
+        }
+        match deadline {
+            Some(deadline) => builder.build_with_progress(|_, _| Instant::now() < deadline),
+            None => builder.build(),
+        }
+    }
+
+    /// Get the error to report when no strategy created a patch.
+    fn failure(attempts: &[BpsAttempt]) -> Error {
+        let failed = attempts.iter().find_map(|attempt| match &attempt.outcome {
+            BpsOutcome::Failed(error) => Some(error.clone()),
+            _ => None,
+        });
+        if let Some(error) = failed {
+            error
+        } else if attempts.iter().all(|attempt| attempt.outcome == BpsOutcome::OverMemory) {
+            Error::OutOfMem
+        } else {
+            Error::Canceled
+        }
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<T: AsRef<[u8]>, M: AsRef<[u8]>> BpsBuilder<MappedFile, T, M> {
+    /// Set the source of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn source_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.source(MappedFile::open(path)?))
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[cfg(feature = "mmap")]
+impl<S: AsRef<[u8]>, M: AsRef<[u8]>> BpsBuilder<S, MappedFile, M> {
+    /// Set the target of the patch to the file at the given path, mapped in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// [`MappedFile`](./struct.MappedFile.html#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn target_file<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<&mut Self> {
+        Ok(self.target(MappedFile::open(path)?))
+    }
+}
@@ -0,0 +1,156 @@
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+use super::BpsInfo;
+use super::BpsPatch;
+use super::BpsWriter;
+
+/// The origin of a region of the intermediate buffer.
+#[derive(Clone, Copy, Debug)]
+enum Origin<'a> {
+    /// Copied from the source of the first patch, at the given offset.
+    Source(u64),
+    /// Stored in the first patch.
+    Literal(&'a [u8]),
+    /// Copied from an earlier position in the intermediate buffer.
+    Target(u64),
+}
+
+/// A region of the intermediate buffer written by an action of the first patch.
+#[derive(Clone, Copy, Debug)]
+struct Segment<'a> {
+    position: u64,
+    len: u64,
+    origin: Origin<'a>,
+}
+
+/// A piece of the composed patch waiting to be written.
+#[derive(Clone, Copy, Debug)]
+enum Piece {
+    /// Copy a region of the intermediate buffer.
+    Intermediate { position: u64, len: u64 },
+    /// Repeat the bytes written to the output starting at `offset`.
+    Repeat { offset: u64, len: u64 },
+}
+
+/// Read the header of a patch, and check the patch checksum.
+fn read_checked(patch: &[u8]) -> Result<(BpsInfo<'_>, BpsActions<'_>)> {
+    let (info, reader) = format::read_info(patch)?;
+    if format::crc32(&patch[..patch.len() - 4]) != info.patch_crc32 {
+        return Err(Error::Invalid);
+    }
+    Ok((info, BpsActions::new(&info, reader)))
+}
+
+/// Compose two BPS patches into a single one.
+///
+/// The actions of the second patch reading from its source are resolved
+/// into the actions of the first patch which created that part of its
+/// source, so the intermediate buffer never needs to be materialized.
+pub fn compose(first: &[u8], second: &[u8]) -> Result<BpsPatch<Vec<u8>>> {
+    let (info_first, actions_first) = read_checked(first)?;
+    let (info_second, actions_second) = read_checked(second)?;
+    if info_first.target_crc32 != info_second.source_crc32 || info_first.target_size != info_second.source_size {
+        return Err(Error::NotThis);
+    }
+
+    // record where each region of the intermediate buffer comes from
+    let mut segments = Vec::new();
+    for item in actions_first {
+        let (position, action) = item?;
+        let origin = match action {
+            BpsAction::SourceRead { .. } => Origin::Source(position),
+            BpsAction::TargetRead { bytes } => Origin::Literal(bytes),
+            BpsAction::SourceCopy { offset, .. } => Origin::Source(offset),
+            BpsAction::TargetCopy { offset, .. } => Origin::Target(offset),
+        };
+        segments.push(Segment { position, len: action.len(), origin });
+    }
+
+    let mut writer = BpsWriter::new(info_first.source_size, info_second.target_size);
+    writer.metadata(info_second.metadata);
+    let mut pieces = Vec::new();
+    for item in actions_second {
+        match item? {
+            (_, BpsAction::TargetRead { bytes }) => {
+                writer.action(BpsAction::TargetRead { bytes })?;
+            }
+            (_, BpsAction::TargetCopy { offset, len }) => {
+                writer.action(BpsAction::TargetCopy { offset, len })?;
+            }
+            (position, BpsAction::SourceRead { len }) => {
+                pieces.push(Piece::Intermediate { position, len });
+                resolve(&segments, &mut pieces, &mut writer)?;
+            }
+            (_, BpsAction::SourceCopy { offset, len }) => {
+                pieces.push(Piece::Intermediate { position: offset, len });
+                resolve(&segments, &mut pieces, &mut writer)?;
+            }
+        }
+    }
+
+    if writer.position() != info_second.target_size {
+        return Err(Error::Invalid);
+    }
+    Ok(writer.finish_unchecked(info_first.source_crc32, info_second.target_crc32))
+}
+
+/// Write the pieces of the intermediate buffer with actions of the first patch.
+///
+/// Pieces are processed from the end of `pieces`, which is used as a stack
+/// to avoid recursing through chains of copies of the intermediate buffer.
+fn resolve(segments: &[Segment], pieces: &mut Vec<Piece>, writer: &mut BpsWriter) -> Result<()> {
+    while let Some(piece) = pieces.pop() {
+        let (position, len) = match piece {
+            Piece::Repeat { offset, len } => {
+                writer.action(BpsAction::TargetCopy { offset, len })?;
+                continue;
+            }
+            Piece::Intermediate { position, len } => (position, len),
+        };
+
+        // find the segment containing the start of the piece, and defer
+        // the part of the piece that is past the end of the segment
+        let index = match segments.binary_search_by_key(&position, |s| s.position) {
+            Ok(index) => index,
+            Err(index) => index.checked_sub(1).ok_or(Error::Invalid)?,
+        };
+        let segment = segments[index];
+        let skip = position - segment.position;
+        let len_here = len.min(segment.len - skip);
+        if len_here < len {
+            pieces.push(Piece::Intermediate { position: position + len_here, len: len - len_here });
+        }
+
+        match segment.origin {
+            Origin::Source(offset) if offset + skip == writer.position() => {
+                writer.action(BpsAction::SourceRead { len: len_here })?;
+            }
+            Origin::Source(offset) => {
+                writer.action(BpsAction::SourceCopy { offset: offset + skip, len: len_here })?;
+            }
+            Origin::Literal(bytes) => {
+                let start = format::to_size(skip)?;
+                let end = format::to_size(skip + len_here)?;
+                writer.action(BpsAction::TargetRead { bytes: &bytes[start..end] })?;
+            }
+            Origin::Target(offset) => {
+                // NB: a copy from an earlier position repeats the bytes of the
+                //     intermediate buffer with a period of `distance`, so only
+                //     one period needs to be resolved, and the rest can be
+                //     copied from the output of the composed patch.
+                let distance = segment.position - offset;
+                let period = len_here.min(distance);
+                if period < len_here {
+                    let offset = writer.position();
+                    pieces.push(Piece::Repeat { offset, len: len_here - period });
+                }
+                pieces.push(Piece::Intermediate { position: offset + skip % distance, len: period });
+            }
+        }
+    }
+    Ok(())
+}
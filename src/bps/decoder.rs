@@ -0,0 +1,134 @@
+//! Pure-Rust implementation of the BPS decoder.
+
+use crate::Error;
+#[cfg(feature = "pure-rust")]
+use crate::FlipsMemory;
+use crate::Result;
+
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+use super::BpsInfo;
+#[cfg(feature = "pure-rust")]
+use super::BpsOutput;
+
+/// Write the output of the given actions to `target`.
+///
+/// `target` must be exactly as large as the target size declared in the
+/// patch header the actions were read from.
+pub fn decode(actions: BpsActions, source: &[u8], target: &mut [u8]) -> Result<()> {
+    for item in actions {
+        let (position, action) = item?;
+        let position = format::to_size(position)?;
+        let end = position + format::to_size(action.len())?;
+        match action {
+            BpsAction::SourceRead { .. } => {
+                let bytes = source.get(position..end).ok_or(Error::Invalid)?;
+                target[position..end].copy_from_slice(bytes);
+            }
+            BpsAction::TargetRead { bytes } => {
+                target[position..end].copy_from_slice(bytes);
+            }
+            BpsAction::SourceCopy { offset, len } => {
+                let offset = format::to_size(offset)?;
+                let bytes = source.get(offset..offset + format::to_size(len)?).ok_or(Error::Invalid)?;
+                target[position..end].copy_from_slice(bytes);
+            }
+            BpsAction::TargetCopy { offset, .. } => {
+                // NB: the copied region may overlap with the one being
+                //     written, so bytes must be copied one at a time.
+                let offset = format::to_size(offset)?;
+                for i in 0..end - position {
+                    target[position + i] = target[offset + i];
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the checksum of a patch, and the checksum and size of its source.
+///
+/// Returns the error caused by a wrong source, if any, so that the caller
+/// can decide whether to stop or not.
+pub fn check(patch: &[u8], info: &BpsInfo, source: &[u8]) -> Result<Option<Error>> {
+    if format::crc32(&patch[..patch.len() - 4]) != info.patch_crc32 {
+        return Err(Error::Invalid);
+    }
+    let crc_input = format::crc32(source);
+    if crc_input != info.source_crc32 {
+        Ok(Some(match crc_input == info.target_crc32 {
+            true => Error::ToOutput,
+            false => Error::NotThis,
+        }))
+    } else if source.len() as u64 != info.source_size {
+        Ok(Some(Error::NotThis))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Allocate a zeroed `Vec` for a target of `size` bytes.
+///
+/// The size comes from a patch and cannot be trusted, so an allocation
+/// failure is reported as an error instead of aborting the process.
+#[cfg(feature = "std")]
+pub fn alloc_vec(size: u64) -> Result<Vec<u8>> {
+    let size = format::to_size(size)?;
+    let mut target = Vec::new();
+    target.try_reserve_exact(size).map_err(|_| Error::OutOfMem)?;
+    target.resize(size, 0);
+    Ok(target)
+}
+
+/// Apply a BPS patch to a source, and return the output in a `Vec`.
+#[cfg(feature = "std")]
+pub fn apply_vec(patch: &[u8], source: &[u8]) -> Result<Vec<u8>> {
+    let (info, reader) = format::read_info(patch)?;
+    if let Some(error) = check(patch, &info, source)? {
+        return Err(error);
+    }
+    let mut target = alloc_vec(info.target_size)?;
+    decode(BpsActions::new(&info, reader), source, &mut target)?;
+    if format::crc32(&target) != info.target_crc32 {
+        return Err(Error::NotThis);
+    }
+    Ok(target)
+}
+
+/// Apply a BPS patch to a source, like `bps_apply` would.
+///
+/// If `accept_wrong_input` is `true`, a source or target mismatch does
+/// not stop the application, and the output is returned along with the
+/// error that would have been raised otherwise.
+#[cfg(feature = "pure-rust")]
+pub fn apply(patch: &[u8], source: &[u8], accept_wrong_input: bool) -> Result<(BpsOutput, Option<Error>)> {
+    // check the patch and the source
+    let (info, reader) = format::read_info(patch)?;
+    let mut error = check(patch, &info, source)?;
+    if let (Some(e), false) = (error.as_ref(), accept_wrong_input) {
+        return Err(e.clone());
+    }
+
+    // decode the actions
+    let mut output = FlipsMemory::alloc(format::to_size(info.target_size)?)?;
+    let target = output.as_bytes_mut();
+    decode(BpsActions::new(&info, reader), source, target)?;
+
+    // check the output
+    if format::crc32(target) != info.target_crc32 {
+        if !accept_wrong_input {
+            return Err(Error::NotThis);
+        }
+        error = error.or(Some(Error::NotThis));
+    }
+
+    let output = if info.metadata.is_empty() {
+        BpsOutput::from(output)
+    } else {
+        let mut mem_m = FlipsMemory::alloc(info.metadata.len())?;
+        mem_m.as_bytes_mut().copy_from_slice(info.metadata);
+        BpsOutput::with_metadata(output, mem_m)
+    };
+    Ok((output, error))
+}
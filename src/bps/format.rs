@@ -0,0 +1,178 @@
+//! Low-level helpers to read the BPS binary format.
+
+use core::convert::TryFrom;
+
+use crate::Error;
+use crate::Result;
+
+use super::BpsInfo;
+
+/// The magic bytes starting every BPS patch.
+pub const MAGIC: &[u8] = b"BPS1";
+
+/// The size of the footer storing the checksums at the end of a patch.
+pub const FOOTER_SIZE: usize = 12;
+
+/// The smallest possible size of a BPS patch.
+///
+/// A patch contains at least the magic bytes, three one-byte numbers
+/// for the source, target and metadata sizes, and the footer.
+pub const MIN_SIZE: usize = MAGIC.len() + 3 + FOOTER_SIZE;
+
+/// Read a little-endian `u32` at the given offset in a buffer.
+pub fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Compute the CRC32 checksum of a buffer.
+pub fn crc32(buffer: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(buffer);
+    hasher.finalize()
+}
+
+/// Convert a number read from a patch to a size for the target platform.
+#[cfg(any(feature = "std", feature = "pure-rust"))]
+pub fn to_size(n: u64) -> Result<usize> {
+    usize::try_from(n).map_err(|_| Error::TooBig)
+}
+
+/// Apply an encoded relative offset to an absolute position.
+///
+/// The lowest bit of `encoded` is the sign of the offset, and the other
+/// bits are its absolute value.
+pub fn relative(position: u64, encoded: u64) -> Result<u64> {
+    let distance = encoded >> 1;
+    let result = if encoded & 1 == 0 {
+        position.checked_add(distance)
+    } else {
+        position.checked_sub(distance)
+    };
+    result.ok_or(Error::Invalid)
+}
+
+/// Encode the offset between two absolute positions.
+///
+/// This is the inverse of [`relative`](./fn.relative.html).
+#[cfg(feature = "std")]
+pub fn encode_relative(from: u64, to: u64) -> u64 {
+    if to >= from {
+        (to - from) << 1
+    } else {
+        ((from - to) << 1) | 1
+    }
+}
+
+/// Write a variable-length encoded number.
+#[cfg(feature = "std")]
+pub fn write_number(buffer: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let x = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buffer.push(0x80 | x);
+            return;
+        }
+        buffer.push(x);
+        n -= 1;
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// A cursor over the bytes of a BPS patch.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Create a new reader over the given bytes.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    /// Check whether all the bytes have been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    /// Read a single byte.
+    pub fn byte(&mut self) -> Result<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    /// Read `len` bytes.
+    ///
+    /// If there are not enough bytes left, the reader is exhausted and
+    /// [`is_empty`](#method.is_empty) will return `true` afterwards.
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        match self.position.checked_add(len) {
+            Some(end) if end <= self.data.len() => {
+                let bytes = &self.data[self.position..end];
+                self.position = end;
+                Ok(bytes)
+            }
+            _ => {
+                self.position = self.data.len();
+                Err(Error::Invalid)
+            }
+        }
+    }
+
+    /// Read a variable-length encoded number.
+    pub fn number(&mut self) -> Result<u64> {
+        let mut data = 0u64;
+        let mut shift = 1u64;
+        loop {
+            let x = self.byte()?;
+            data = u64::from(x & 0x7f)
+                .checked_mul(shift)
+                .and_then(|n| data.checked_add(n))
+                .ok_or(Error::Invalid)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or(Error::Invalid)?;
+            data = data.checked_add(shift).ok_or(Error::Invalid)?;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Read the header and the footer of a BPS patch.
+///
+/// Returns the patch information, and a reader positioned on the first
+/// action of the patch, which stops before the footer.
+pub fn read_info(patch: &[u8]) -> Result<(BpsInfo<'_>, Reader<'_>)> {
+    if patch.len() < MIN_SIZE {
+        return Err(Error::Invalid);
+    }
+
+    let footer = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..footer]);
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(Error::Invalid);
+    }
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    let metadata = match usize::try_from(metadata_size) {
+        Ok(len) => reader.bytes(len)?,
+        Err(_) => return Err(Error::Invalid),
+    };
+
+    let info = BpsInfo {
+        source_size,
+        target_size,
+        metadata,
+        source_crc32: read_u32(patch, footer),
+        target_crc32: read_u32(patch, footer + 4),
+        patch_crc32: read_u32(patch, footer + 8),
+    };
+    Ok((info, reader))
+}
@@ -0,0 +1,78 @@
+use crate::Error;
+use crate::Result;
+
+use super::decoder;
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+use super::BpsPatch;
+use super::BpsWriter;
+
+/// A region of the target copied verbatim from the source.
+#[derive(Clone, Copy, Debug)]
+struct Mapping {
+    source: u64,
+    target: u64,
+    len: u64,
+}
+
+/// Create the inverse of a BPS patch, using the source it applies to.
+pub fn invert(patch: &[u8], source: &[u8]) -> Result<BpsPatch<Vec<u8>>> {
+    let target = decoder::apply_vec(patch, source)?;
+    let (info, reader) = format::read_info(patch)?;
+
+    // collect the regions of the target that were copied from the source
+    let mut mappings = Vec::new();
+    for item in BpsActions::new(&info, reader) {
+        match item? {
+            (position, BpsAction::SourceRead { len }) => {
+                mappings.push(Mapping { source: position, target: position, len });
+            }
+            (position, BpsAction::SourceCopy { offset, len }) => {
+                mappings.push(Mapping { source: offset, target: position, len });
+            }
+            _ => (),
+        }
+    }
+    mappings.sort_by_key(|m| m.source);
+
+    // cover the source with the longest mappings, and store the gaps
+    let mut writer = BpsWriter::new(info.target_size, info.source_size);
+    writer.metadata(info.metadata);
+    let mut best: Option<Mapping> = None;
+    let mut i = 0;
+    while writer.position() < info.source_size {
+        let position = writer.position();
+        while i < mappings.len() && mappings[i].source <= position {
+            let m = mappings[i];
+            if best.map(|b| b.source + b.len < m.source + m.len).unwrap_or(true) {
+                best = Some(m);
+            }
+            i += 1;
+        }
+        match best {
+            Some(m) if m.source + m.len > position => {
+                let offset = m.target + (position - m.source);
+                let len = m.source + m.len - position;
+                if offset == position {
+                    writer.action(BpsAction::SourceRead { len })?;
+                } else {
+                    writer.action(BpsAction::SourceCopy { offset, len })?;
+                }
+            }
+            _ => {
+                let end = mappings.get(i).map(|m| m.source).unwrap_or(info.source_size);
+                let bytes = &source[format::to_size(position)?..format::to_size(end)?];
+                writer.action(BpsAction::TargetRead { bytes })?;
+            }
+        }
+    }
+
+    // make sure the inverse patch reverts the original one
+    let inverse = writer.finish(&target)?;
+    let inverse_info = inverse.info()?;
+    if inverse_info.source_crc32 != info.target_crc32 || inverse_info.target_crc32 != info.source_crc32 {
+        return Err(Error::Invalid);
+    }
+    Ok(inverse)
+}
@@ -0,0 +1,320 @@
+use core::str;
+
+use crate::text::check_depth;
+use crate::text::fields;
+use crate::text::Cursor;
+use crate::text::Fields;
+
+/// The root element of a serialized manifest.
+const ROOT: &str = "patch";
+
+/// The metadata embedded in a BPS patch.
+///
+/// Metadata are free-form, but the convention established by `beat` and
+/// followed by Flips is to store an XML manifest describing the patch. This
+/// type parses such manifests, and keeps any other metadata as raw bytes.
+///
+/// # Example
+/// ```rust
+/// use flips::BpsManifest;
+/// use flips::BpsMetadata;
+///
+/// let mut manifest = BpsManifest::default();
+/// manifest.title = Some(String::from("My Romhack"));
+/// manifest.author = Some(String::from("Someone"));
+/// let bytes = BpsMetadata::Manifest(manifest.clone()).to_bytes();
+///
+/// match BpsMetadata::parse(&bytes) {
+///     BpsMetadata::Manifest(m) => assert_eq!(m, manifest),
+///     BpsMetadata::Raw(_) => unreachable!(),
+/// }
+/// ```
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BpsMetadata {
+    /// An XML manifest.
+    Manifest(BpsManifest),
+    /// Metadata in any other format.
+    Raw(Vec<u8>),
+}
+
+impl BpsMetadata {
+    /// Parse metadata from raw bytes.
+    ///
+    /// Metadata which are not an XML document encoded in UTF-8 are returned
+    /// as [`BpsMetadata::Raw`](#variant.Raw).
+    pub fn parse<B: AsRef<[u8]>>(bytes: B) -> Self {
+        let bytes = bytes.as_ref();
+        str::from_utf8(bytes)
+            .ok()
+            .and_then(|text| Parser::new(text).document())
+            .map(BpsMetadata::Manifest)
+            .unwrap_or_else(|| BpsMetadata::Raw(bytes.to_vec()))
+    }
+
+    /// Serialize the metadata to raw bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            BpsMetadata::Manifest(manifest) => manifest.to_string().into_bytes(),
+            BpsMetadata::Raw(bytes) => bytes.clone(),
+        }
+    }
+}
+
+impl From<BpsManifest> for BpsMetadata {
+    fn from(manifest: BpsManifest) -> Self {
+        BpsMetadata::Manifest(manifest)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// An XML manifest describing a BPS patch.
+///
+/// Elements with text content are read wherever they appear in the document,
+/// so that both flat manifests and manifests grouping the fields in nested
+/// elements can be read. Elements other than the known fields are stored in
+/// [`extra`](#structfield.extra), in the order they appear in the document.
+///
+/// Serializing a parsed manifest and parsing it again gives back the same
+/// manifest. This does not hold for every manifest built by hand: a
+/// [`text`](#structfield.text) made only of whitespace is read back as
+/// `None`, and an extra field named like a known field which is `None` is
+/// read back into that known field.
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BpsManifest {
+    /// The name of the root element, `patch` by default.
+    pub root: String,
+    /// The text directly under the root element, if it is not only made of
+    /// whitespace.
+    pub text: Option<String>,
+    /// The title of the patch.
+    pub title: Option<String>,
+    /// The author of the patch.
+    pub author: Option<String>,
+    /// The version of the patch.
+    pub version: Option<String>,
+    /// A description of the patch.
+    pub description: Option<String>,
+    /// Additional fields, as pairs of element names and text contents.
+    ///
+    /// Names are written as is when serializing, so they must be valid XML
+    /// element names.
+    pub extra: Vec<(String, String)>,
+}
+
+impl Default for BpsManifest {
+    fn default() -> Self {
+        Self {
+            root: String::from(ROOT),
+            text: None,
+            title: None,
+            author: None,
+            version: None,
+            description: None,
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl Fields for BpsManifest {
+    fn field(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "title" => Some(&mut self.title),
+            "author" => Some(&mut self.author),
+            "version" => Some(&mut self.version),
+            "description" => Some(&mut self.description),
+            _ => None,
+        }
+    }
+
+    fn extra(&mut self) -> &mut Vec<(String, String)> {
+        &mut self.extra
+    }
+}
+
+impl core::fmt::Display for BpsManifest {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        // NB: the fields are only indented when the root has no text, since
+        //     the indentation would be read back as part of the text.
+        let (indent, newline) = match &self.text {
+            Some(_) => ("", ""),
+            None => ("  ", "\n"),
+        };
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        write!(f, "<{}>{}", self.root, newline)?;
+        if let Some(text) = &self.text {
+            escape(f, text)?;
+        }
+        let known = [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("version", &self.version),
+            ("description", &self.description),
+        ];
+        for (name, value) in fields(&known, &self.extra) {
+            write!(f, "{}<{}>", indent, name)?;
+            escape(f, value)?;
+            write!(f, "</{}>{}", name, newline)?;
+        }
+        writeln!(f, "</{}>", self.root)
+    }
+}
+
+/// Write `text` with the characters reserved by XML replaced by entities.
+fn escape(f: &mut core::fmt::Formatter, text: &str) -> core::fmt::Result {
+    for c in text.chars() {
+        match c {
+            '&' => f.write_str("&amp;")?,
+            '<' => f.write_str("&lt;")?,
+            '>' => f.write_str("&gt;")?,
+            _ => write!(f, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+
+/// A minimal XML parser, reading the text content of elements.
+struct Parser<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { cursor: Cursor::new(text) }
+    }
+
+    /// Skip whitespace, comments and processing instructions.
+    fn misc(&mut self) -> Option<()> {
+        loop {
+            self.cursor.skip_whitespace();
+            if self.cursor.eat("<?") {
+                self.cursor.until("?>")?;
+            } else if self.cursor.eat("<!--") {
+                self.cursor.until("-->")?;
+            } else if self.cursor.eat("<!") {
+                self.cursor.until(">")?;
+            } else {
+                return Some(());
+            }
+        }
+    }
+
+    /// Read a whole document.
+    fn document(&mut self) -> Option<BpsManifest> {
+        let mut manifest = BpsManifest::default();
+        self.misc()?;
+        self.element(&mut manifest, 0)?;
+        self.misc()?;
+        match self.cursor.text.is_empty() {
+            true => Some(manifest),
+            false => None,
+        }
+    }
+
+    /// Read an element name.
+    fn name(&mut self) -> Option<&'a str> {
+        let name = self.cursor.take_until(|c: char| c.is_whitespace() || c == '/' || c == '>');
+        match name.is_empty() {
+            true => None,
+            false => Some(name),
+        }
+    }
+
+    /// Read an element and its children, recording text contents.
+    fn element(&mut self, manifest: &mut BpsManifest, depth: usize) -> Option<()> {
+        check_depth(depth)?;
+        if !self.cursor.eat("<") {
+            return None;
+        }
+        let name = self.name()?;
+
+        // skip attributes, which may contain `>` in quoted values
+        loop {
+            self.cursor.skip_whitespace();
+            if self.cursor.eat("/>") {
+                match depth {
+                    0 => manifest.root = name.to_string(),
+                    _ => manifest.insert(name, String::new()),
+                }
+                return Some(());
+            } else if self.cursor.eat(">") {
+                break;
+            } else if self.cursor.eat("\"") {
+                self.cursor.until("\"")?;
+            } else if self.cursor.eat("'") {
+                self.cursor.until("'")?;
+            } else {
+                let c = self.cursor.text.chars().next()?;
+                self.cursor.text = &self.cursor.text[c.len_utf8()..];
+            }
+        }
+
+        // read the content
+        let mut text = String::new();
+        let mut children = false;
+        loop {
+            if self.cursor.eat("</") {
+                if self.name()? != name {
+                    return None;
+                }
+                self.cursor.skip_whitespace();
+                if !self.cursor.eat(">") {
+                    return None;
+                }
+                break;
+            } else if self.cursor.eat("<![CDATA[") {
+                text.push_str(self.cursor.until("]]>")?);
+            } else if self.cursor.eat("<!--") {
+                self.cursor.until("-->")?;
+            } else if self.cursor.eat("<?") {
+                self.cursor.until("?>")?;
+            } else if self.cursor.text.starts_with('<') {
+                children = true;
+                self.element(manifest, depth + 1)?;
+            } else {
+                let end = self.cursor.text.find('<')?;
+                unescape(&self.cursor.text[..end], &mut text)?;
+                self.cursor.text = &self.cursor.text[end..];
+            }
+        }
+
+        if depth == 0 {
+            manifest.root = name.to_string();
+            if !text.trim().is_empty() {
+                manifest.text = Some(text);
+            }
+        } else if !children {
+            manifest.insert(name, text);
+        }
+        Some(())
+    }
+}
+
+/// Replace the XML entities of `text`, and append the result to `output`.
+fn unescape(text: &str, output: &mut String) -> Option<()> {
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        output.push_str(&rest[..i]);
+        let end = rest[i..].find(';')? + i;
+        let c = match &rest[i + 1..end] {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            entity if entity.starts_with("#x") => {
+                core::char::from_u32(u32::from_str_radix(&entity[2..], 16).ok()?)?
+            }
+            entity if entity.starts_with('#') => core::char::from_u32(entity[1..].parse().ok()?)?,
+            _ => return None,
+        };
+        output.push(c);
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    Some(())
+}
@@ -0,0 +1,139 @@
+use core::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::Error;
+use crate::FlipsMemory;
+use crate::Result;
+
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+use super::BpsWriter;
+
+/// The minimum size of the chunks the target is split into.
+///
+/// Each chunk is diffed separately, so chunks that are too small would
+/// lose too many matches, and spend more time in setup than in searching.
+pub const MIN_CHUNK_SIZE: usize = 1 << 20;
+
+/// The delta of a chunk of the target against a window of the source.
+enum Chunk {
+    /// The chunk is a copy of the source window.
+    Identical,
+    /// The chunk is built from the actions of a patch.
+    Patch(Vec<u8>),
+}
+
+/// Get the window of the source used to diff the target chunk in `range`.
+///
+/// The window spans the source region at the same offsets as the chunk,
+/// extended by the size of the chunk on both sides, so that data that moved
+/// by less than the size of a chunk can still be found.
+fn window(range: &Range<usize>, source_size: usize) -> Range<usize> {
+    let margin = range.len();
+    let start = range.start.min(source_size).saturating_sub(margin);
+    let end = range.end.saturating_add(margin).min(source_size);
+    start..end
+}
+
+/// Create a delta patch from `source` to `target` using up to `threads` threads.
+pub fn create_delta(
+    source: &[u8],
+    target: &[u8],
+    metadata: Option<&[u8]>,
+    moremem: bool,
+    threads: usize,
+) -> Result<FlipsMemory> {
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool,
+        Err(_) => return super::create_delta(source, target, metadata, None, core::ptr::null_mut(), moremem),
+    };
+
+    let count = pool.current_num_threads().min(target.len() / MIN_CHUNK_SIZE);
+    if count <= 1 {
+        return super::create_delta(source, target, metadata, None, core::ptr::null_mut(), moremem);
+    } else if source == target {
+        return Err(Error::Identical);
+    }
+
+    // diff each chunk of the target against its window in the source
+    let chunk_size = target.len().div_ceil(count);
+    let ranges = (0..count)
+        .map(|i| i * chunk_size..((i + 1) * chunk_size).min(target.len()))
+        .collect::<Vec<_>>();
+    let chunks = pool.install(|| {
+        ranges
+            .par_iter()
+            .map(|range| {
+                let window = window(range, source.len());
+                let mem = super::create_delta(
+                    &source[window],
+                    &target[range.clone()],
+                    None,
+                    None,
+                    core::ptr::null_mut(),
+                    moremem,
+                );
+                match mem {
+                    Ok(mem) => Ok(Chunk::Patch(mem.to_bytes())),
+                    Err(Error::Identical) => Ok(Chunk::Identical),
+                    Err(e) => Err(e),
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    // concatenate the actions of each chunk, moving them to the position
+    // of the chunk in the target and of the window in the source
+    let mut writer = BpsWriter::new(source.len() as u64, target.len() as u64);
+    if let Some(m) = metadata {
+        writer.metadata(m);
+    }
+    for (range, chunk) in ranges.iter().zip(&chunks) {
+        let window = window(range, source.len());
+        let (source_start, target_start) = (window.start as u64, range.start as u64);
+        let patch = match chunk {
+            Chunk::Patch(patch) => patch,
+            Chunk::Identical => {
+                copy_source(&mut writer, source_start, range.len() as u64)?;
+                continue;
+            }
+        };
+        let (info, reader) = format::read_info(patch)?;
+        for item in BpsActions::new(&info, reader) {
+            match item? {
+                (position, BpsAction::SourceRead { len }) => {
+                    copy_source(&mut writer, source_start + position, len)?;
+                }
+                (_, BpsAction::SourceCopy { offset, len }) => {
+                    copy_source(&mut writer, source_start + offset, len)?;
+                }
+                (_, BpsAction::TargetCopy { offset, len }) => {
+                    writer.action(BpsAction::TargetCopy { offset: target_start + offset, len })?;
+                }
+                (_, action @ BpsAction::TargetRead { .. }) => {
+                    writer.action(action)?;
+                }
+            }
+        }
+    }
+
+    // all chunks were checked by `libbps`, so the checksums of the whole
+    // buffers can be used without replaying the concatenated actions
+    let patch = writer.finish_unchecked(format::crc32(source), format::crc32(target));
+    let bytes = patch.as_ref();
+    let mut mem = FlipsMemory::alloc(bytes.len())?;
+    mem.as_bytes_mut().copy_from_slice(bytes);
+    Ok(mem)
+}
+
+/// Copy `len` bytes of the source at `offset` to the target.
+fn copy_source(writer: &mut BpsWriter, offset: u64, len: u64) -> Result<()> {
+    if offset == writer.position() {
+        writer.action(BpsAction::SourceRead { len })?;
+    } else {
+        writer.action(BpsAction::SourceCopy { offset, len })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::Result;
+
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+
+/// The number of actions of a given kind in a BPS patch, and the bytes they write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BpsActionStats {
+    /// The number of actions of this kind.
+    pub count: u64,
+    /// The total number of bytes written to the output by these actions.
+    pub bytes: u64,
+}
+
+impl BpsActionStats {
+    /// Record an action writing `len` bytes.
+    fn add(&mut self, len: u64) {
+        self.count += 1;
+        self.bytes += len;
+    }
+}
+
+/// Statistics about the content of a BPS patch.
+///
+/// # Example
+/// ```rust
+/// # let bytes = include_bytes!("../../tests/data/patch1to2.bps");
+/// let patch = flips::BpsPatch::new(&bytes[..]);
+/// let stats = patch.stats().expect("invalid patch");
+/// println!("{} actions, {:.1}% of the target", stats.actions(), 100.0 * stats.ratio());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BpsStats {
+    /// The `SourceRead` actions of the patch.
+    pub source_read: BpsActionStats,
+    /// The `TargetRead` actions of the patch.
+    pub target_read: BpsActionStats,
+    /// The `SourceCopy` actions of the patch.
+    pub source_copy: BpsActionStats,
+    /// The `TargetCopy` actions of the patch.
+    pub target_copy: BpsActionStats,
+    /// The size of the metadata stored in the patch.
+    pub metadata_size: u64,
+    /// The size of the whole patch.
+    pub patch_size: u64,
+    /// The size of the target the patch creates.
+    pub target_size: u64,
+}
+
+impl BpsStats {
+    /// Get the total number of actions in the patch.
+    pub fn actions(&self) -> u64 {
+        self.source_read.count + self.target_read.count + self.source_copy.count + self.target_copy.count
+    }
+
+    /// Get the average number of bytes written by `SourceCopy` and `TargetCopy` actions.
+    ///
+    /// Returns `0.0` if the patch has no copy actions.
+    pub fn average_copy_len(&self) -> f64 {
+        let count = self.source_copy.count + self.target_copy.count;
+        match count {
+            0 => 0.0,
+            _ => (self.source_copy.bytes + self.target_copy.bytes) as f64 / count as f64,
+        }
+    }
+
+    /// Get the ratio of the patch size to the target size.
+    ///
+    /// Returns `0.0` if the target is empty.
+    pub fn ratio(&self) -> f64 {
+        match self.target_size {
+            0 => 0.0,
+            _ => self.patch_size as f64 / self.target_size as f64,
+        }
+    }
+}
+
+/// Compute the statistics of a patch by reading all of its actions.
+pub fn stats(patch: &[u8]) -> Result<BpsStats> {
+    let (info, reader) = format::read_info(patch)?;
+    let mut stats = BpsStats {
+        metadata_size: info.metadata.len() as u64,
+        patch_size: patch.len() as u64,
+        target_size: info.target_size,
+        ..Default::default()
+    };
+    for item in BpsActions::new(&info, reader) {
+        let (_, action) = item?;
+        let len = action.len();
+        match action {
+            BpsAction::SourceRead { .. } => stats.source_read.add(len),
+            BpsAction::TargetRead { .. } => stats.target_read.add(len),
+            BpsAction::SourceCopy { .. } => stats.source_copy.add(len),
+            BpsAction::TargetCopy { .. } => stats.target_copy.add(len),
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,123 @@
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+
+/// The size of the buffer used to copy data between streams.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Apply a BPS patch from a source stream to a target stream.
+pub fn apply<R, W>(patch: &[u8], mut source: R, mut target: W) -> Result<()>
+where
+    R: Read + Seek,
+    W: Read + Write + Seek,
+{
+    let mut buffer = vec![0; CHUNK_SIZE];
+
+    // check the checksums of the patch and of the source
+    let (info, reader) = format::read_info(patch)?;
+    if format::crc32(&patch[..patch.len() - 4]) != info.patch_crc32 {
+        return Err(Error::Invalid);
+    }
+    let mut hasher = crc32fast::Hasher::new();
+    let mut source_size = 0u64;
+    source.seek(SeekFrom::Start(0))?;
+    loop {
+        match source.read(&mut buffer)? {
+            0 => break,
+            n => {
+                hasher.update(&buffer[..n]);
+                source_size += n as u64;
+            }
+        }
+    }
+    let crc_input = hasher.finalize();
+    if crc_input != info.source_crc32 {
+        return Err(if crc_input == info.target_crc32 {
+            Error::ToOutput
+        } else {
+            Error::NotThis
+        });
+    }
+    if source_size != info.source_size {
+        return Err(Error::NotThis);
+    }
+
+    // decode the actions, hashing the output as it is written
+    let mut hasher = crc32fast::Hasher::new();
+    target.seek(SeekFrom::Start(0))?;
+    for item in BpsActions::new(&info, reader) {
+        let (position, action) = item?;
+        match action {
+            BpsAction::SourceRead { len } => {
+                source.seek(SeekFrom::Start(position))?;
+                copy(&mut source, &mut target, &mut hasher, &mut buffer, len)?;
+            }
+            BpsAction::TargetRead { bytes } => {
+                target.write_all(bytes)?;
+                hasher.update(bytes);
+            }
+            BpsAction::SourceCopy { offset, len } => {
+                source.seek(SeekFrom::Start(offset))?;
+                copy(&mut source, &mut target, &mut hasher, &mut buffer, len)?;
+            }
+            BpsAction::TargetCopy { offset, len } => {
+                let distance = position - offset;
+                let mut copied = 0;
+                while copied < len {
+                    // NB: only the bytes before the current position can be
+                    //     read back from the target, the rest of the chunk
+                    //     repeats them with a period of `distance`.
+                    let n = (len - copied).min(CHUNK_SIZE as u64) as usize;
+                    let k = (n as u64).min(distance) as usize;
+                    target.seek(SeekFrom::Start(offset + copied))?;
+                    target.read_exact(&mut buffer[..k])?;
+                    for i in k..n {
+                        buffer[i] = buffer[i - k];
+                    }
+                    target.seek(SeekFrom::Start(position + copied))?;
+                    target.write_all(&buffer[..n])?;
+                    hasher.update(&buffer[..n]);
+                    copied += n as u64;
+                }
+            }
+        }
+    }
+
+    // check the output, and that no stale bytes remain past its end
+    target.flush()?;
+    if hasher.finalize() != info.target_crc32 {
+        return Err(Error::NotThis);
+    }
+    if target.seek(SeekFrom::End(0))? > info.target_size {
+        return Err(Error::Io(ErrorKind::InvalidInput.into()));
+    }
+    Ok(())
+}
+
+/// Copy `len` bytes from `source` to `target` through `buffer`.
+fn copy<R: Read, W: Write>(
+    source: &mut R,
+    target: &mut W,
+    hasher: &mut crc32fast::Hasher,
+    buffer: &mut [u8],
+    len: u64,
+) -> Result<()> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(buffer.len() as u64) as usize;
+        source.read_exact(&mut buffer[..n])?;
+        target.write_all(&buffer[..n])?;
+        hasher.update(&buffer[..n]);
+        remaining -= n as u64;
+    }
+    Ok(())
+}
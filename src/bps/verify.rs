@@ -0,0 +1,45 @@
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::BpsActions;
+
+/// Read the whole patch, and check it was not truncated or corrupted.
+pub fn verify(patch: &[u8]) -> Result<()> {
+    if !patch.starts_with(format::MAGIC) {
+        return Err(match format::MAGIC.starts_with(patch) {
+            true => Error::Truncated,
+            false => Error::BadMagic,
+        });
+    }
+    if patch.len() < format::MIN_SIZE {
+        return Err(Error::Truncated);
+    }
+
+    // NB: a corrupted patch may still be read without errors, and a
+    //     truncated patch will have a wrong checksum, so the outcome of
+    //     reading the actions must be combined with the checksum check.
+    let footer = patch.len() - 4;
+    let checksum_ok = format::crc32(&patch[..footer]) == format::read_u32(patch, footer);
+    match (walk(patch), checksum_ok) {
+        (Ok(()), true) => Ok(()),
+        (Err(_), true) => Err(Error::Invalid),
+        (Err(true), false) => Err(Error::Truncated),
+        (_, false) => Err(Error::BadChecksum),
+    }
+}
+
+/// Read all the actions of a patch.
+///
+/// On error, returns whether the patch ended before the whole output was
+/// described.
+fn walk(patch: &[u8]) -> core::result::Result<(), bool> {
+    let (info, reader) = format::read_info(patch).map_err(|_| true)?;
+    let mut actions = BpsActions::new(&info, reader);
+    for item in actions.by_ref() {
+        if item.is_err() {
+            return Err(actions.is_exhausted());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::Error;
+use crate::Result;
+
+use super::decoder;
+use super::format;
+use super::BpsAction;
+use super::BpsActions;
+use super::BpsPatch;
+
+/// A writer to assemble a BPS patch from individual actions.
+///
+/// Unlike [`BpsLinearBuilder`] and [`BpsDeltaBuilder`], this type does not
+/// search for differences between a source and a target, but encodes the
+/// actions it is given as they are. Actions are checked when they are
+/// added, so that the resulting patch is always valid.
+///
+/// # Example
+/// ```rust
+/// use flips::BpsAction;
+///
+/// let source = b"Hello, world!";
+/// let mut writer = flips::BpsWriter::new(source.len() as u64, 14);
+/// writer.action(BpsAction::SourceRead { len: 7 })?;
+/// writer.action(BpsAction::TargetRead { bytes: b"Rust" })?;
+/// writer.action(BpsAction::SourceCopy { offset: 12, len: 1 })?;
+/// writer.action(BpsAction::TargetCopy { offset: 11, len: 2 })?;
+///
+/// let patch = writer.finish(&source[..])?;
+/// let output = patch.apply(&source[..])?;
+/// assert_eq!(output.as_ref(), b"Hello, Rust!!!");
+/// # Ok::<(), flips::Error>(())
+/// ```
+///
+/// [`BpsLinearBuilder`]: ./struct.BpsLinearBuilder.html
+/// [`BpsDeltaBuilder`]: ./struct.BpsDeltaBuilder.html
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug)]
+pub struct BpsWriter {
+    source_size: u64,
+    target_size: u64,
+    metadata: Vec<u8>,
+    actions: Vec<u8>,
+    position: u64,
+    source_offset: u64,
+    target_offset: u64,
+}
+
+impl BpsWriter {
+    /// Create a new writer for a patch between buffers of the given sizes.
+    pub fn new(source_size: u64, target_size: u64) -> Self {
+        Self {
+            source_size,
+            target_size,
+            metadata: Vec::new(),
+            actions: Vec::new(),
+            position: 0,
+            source_offset: 0,
+            target_offset: 0,
+        }
+    }
+
+    /// Set the metadata buffer for the patch.
+    pub fn metadata<M: AsRef<[u8]>>(&mut self, metadata: M) -> &mut Self {
+        self.metadata = metadata.as_ref().to_vec();
+        self
+    }
+
+    /// Get the position in the target where the next action will write.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Add an action to the patch.
+    ///
+    /// # Error
+    /// If the action writes no bytes, past the end of the target, or if it
+    /// reads past the end of the source or after the current position in the
+    /// target, this method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn action(&mut self, action: BpsAction) -> Result<&mut Self> {
+        let len = action.len();
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| len > 0 && end <= self.target_size)
+            .ok_or(Error::Invalid)?;
+
+        match action {
+            BpsAction::SourceRead { .. } => {
+                if end > self.source_size {
+                    return Err(Error::Invalid);
+                }
+                self.push(0, len);
+            }
+            BpsAction::TargetRead { bytes } => {
+                self.push(1, len);
+                self.actions.extend_from_slice(bytes);
+            }
+            BpsAction::SourceCopy { offset, .. } => {
+                let source_end = offset
+                    .checked_add(len)
+                    .filter(|&end| end <= self.source_size)
+                    .ok_or(Error::Invalid)?;
+                self.push(2, len);
+                format::write_number(&mut self.actions, format::encode_relative(self.source_offset, offset));
+                self.source_offset = source_end;
+            }
+            BpsAction::TargetCopy { offset, .. } => {
+                if offset >= self.position {
+                    return Err(Error::Invalid);
+                }
+                self.push(3, len);
+                format::write_number(&mut self.actions, format::encode_relative(self.target_offset, offset));
+                self.target_offset = offset + len;
+            }
+        }
+
+        self.position = end;
+        Ok(self)
+    }
+
+    /// Write the header of an action to the patch.
+    fn push(&mut self, command: u64, len: u64) {
+        format::write_number(&mut self.actions, ((len - 1) << 2) | command);
+    }
+
+    /// Finish the patch, using `source` to compute its checksums.
+    ///
+    /// # Error
+    /// If the actions do not cover the whole target, this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid). If the size of
+    /// `source` is not the one the writer was created with, this method will
+    /// return [`Error::NotThis`](./enum.Error.html#variant.NotThis). If the
+    /// target cannot be allocated to compute its checksum, this method will
+    /// return [`Error::OutOfMem`](./enum.Error.html#variant.OutOfMem).
+    pub fn finish<S: AsRef<[u8]>>(&self, source: S) -> Result<BpsPatch<Vec<u8>>> {
+        let source = source.as_ref();
+        if self.position != self.target_size {
+            return Err(Error::Invalid);
+        }
+        if source.len() as u64 != self.source_size {
+            return Err(Error::NotThis);
+        }
+
+        // replay the actions to compute the target checksum
+        let patch = self.encode();
+        let mut target = decoder::alloc_vec(self.target_size)?;
+        let (info, reader) = format::read_info(&patch)?;
+        decoder::decode(BpsActions::new(&info, reader), source, &mut target)?;
+
+        Ok(Self::seal(patch, format::crc32(source), format::crc32(&target)))
+    }
+
+    /// Finish the patch with known source and target checksums.
+    ///
+    /// The caller is responsible for checking the actions cover the whole
+    /// target, and that the checksums are the right ones.
+    pub(super) fn finish_unchecked(&self, source_crc32: u32, target_crc32: u32) -> BpsPatch<Vec<u8>> {
+        Self::seal(self.encode(), source_crc32, target_crc32)
+    }
+
+    /// Encode the patch, leaving room for the footer.
+    fn encode(&self) -> Vec<u8> {
+        let mut patch = Vec::with_capacity(self.actions.len() + self.metadata.len() + 32);
+        patch.extend_from_slice(format::MAGIC);
+        format::write_number(&mut patch, self.source_size);
+        format::write_number(&mut patch, self.target_size);
+        format::write_number(&mut patch, self.metadata.len() as u64);
+        patch.extend_from_slice(&self.metadata);
+        patch.extend_from_slice(&self.actions);
+        patch.extend_from_slice(&[0; format::FOOTER_SIZE]);
+        patch
+    }
+
+    /// Write the footer of an encoded patch.
+    fn seal(mut patch: Vec<u8>, source_crc32: u32, target_crc32: u32) -> BpsPatch<Vec<u8>> {
+        let footer = patch.len() - format::FOOTER_SIZE;
+        patch[footer..footer + 4].copy_from_slice(&source_crc32.to_le_bytes());
+        patch[footer + 4..footer + 8].copy_from_slice(&target_crc32.to_le_bytes());
+        let crc_patch = format::crc32(&patch[..footer + 8]);
+        patch[footer + 8..].copy_from_slice(&crc_patch.to_le_bytes());
+        BpsPatch::new(patch)
+    }
+}
@@ -0,0 +1,180 @@
+use core::ops::Range;
+
+use crate::BpsPatch;
+use crate::Error;
+use crate::IpsPatch;
+use crate::Result;
+use crate::UpsPatch;
+
+/// A patch of any supported format.
+///
+/// This type is used by APIs working on several patches at once, such as
+/// [`Conflict::find`](./struct.Conflict.html#method.find).
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnyPatch<'a> {
+    /// A patch in the IPS format.
+    Ips(IpsPatch<&'a [u8]>),
+    /// A patch in the UPS format.
+    Ups(UpsPatch<&'a [u8]>),
+    /// A patch in the BPS format.
+    Bps(BpsPatch<&'a [u8]>),
+}
+
+impl<'a> AnyPatch<'a> {
+    /// Apply the patch to a source, and get a copy of the output.
+    fn apply(&self, source: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            AnyPatch::Ips(patch) => patch.apply(source).map(|output| output.as_ref().to_vec()),
+            AnyPatch::Ups(patch) => patch.apply(source).map(|output| output.as_ref().to_vec()),
+            AnyPatch::Bps(patch) => patch.apply(source).map(|output| output.as_ref().to_vec()),
+        }
+    }
+}
+
+impl<'a, B: AsRef<[u8]>> From<&'a IpsPatch<B>> for AnyPatch<'a> {
+    fn from(patch: &'a IpsPatch<B>) -> Self {
+        AnyPatch::Ips(IpsPatch::new(patch.as_ref()))
+    }
+}
+
+impl<'a, B: AsRef<[u8]>> From<&'a UpsPatch<B>> for AnyPatch<'a> {
+    fn from(patch: &'a UpsPatch<B>) -> Self {
+        AnyPatch::Ups(UpsPatch::new(patch.as_ref()))
+    }
+}
+
+impl<'a, B: AsRef<[u8]>> From<&'a BpsPatch<B>> for AnyPatch<'a> {
+    fn from(patch: &'a BpsPatch<B>) -> Self {
+        AnyPatch::Bps(BpsPatch::new(patch.as_ref()))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// A range of bytes changed by two patches made for the same source.
+///
+/// Patches are compared by applying each of them to the source on its
+/// own: the bytes changed by a patch are the bytes where its output differs
+/// from the source, including bytes past the end of the source, and bytes
+/// removed when the output is shorter than the source.
+///
+/// # Example
+/// ```rust
+/// # let source = vec![0; 16];
+/// # let bytes1 = flips::IpsBuilder::new().source(&source[..]).target(&b"\0\0abc\0\0\0\0\0\0\0\0\0\0\0"[..]).build()?;
+/// # let bytes2 = flips::IpsBuilder::new().source(&source[..]).target(&b"\0\0\0\0xyz\0\0\0\0\0\0\0\0\0"[..]).build()?;
+/// let translation = flips::IpsPatch::new(bytes1.as_ref());
+/// let bugfix = flips::IpsPatch::new(bytes2.as_ref());
+/// let patches = [(&translation).into(), (&bugfix).into()];
+/// for conflict in flips::Conflict::find(&source, &patches)? {
+///     println!(
+///         "patches {} and {} both write bytes {:?}",
+///         conflict.patches[0], conflict.patches[1], conflict.range
+///     );
+/// }
+/// # Ok::<(), flips::Error>(())
+/// ```
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The range of bytes changed by both patches.
+    pub range: Range<usize>,
+    /// The indices of both patches, in the order they were given.
+    pub patches: [usize; 2],
+    /// The bytes written over the range by each patch.
+    ///
+    /// A value is shorter than the range when the output of the patch
+    /// ends within the range.
+    pub values: [Vec<u8>; 2],
+}
+
+impl Conflict {
+    /// Find the conflicts between patches made for the same source.
+    ///
+    /// Every pair of patches changing the same bytes is reported, in the
+    /// order of the patches and then of the ranges. IPS patches which do not
+    /// change the source are ignored.
+    ///
+    /// # Error
+    /// If a patch cannot be applied to the source, its error is returned.
+    /// In particular, UPS and BPS patches return
+    /// [`Error::ToOutput`](./enum.Error.html#variant.ToOutput) when the
+    /// source is already patched, since it then matches their target.
+    pub fn find<S: AsRef<[u8]>>(source: S, patches: &[AnyPatch]) -> Result<Vec<Conflict>> {
+        let source = source.as_ref();
+        let mut outputs = Vec::with_capacity(patches.len());
+        for patch in patches {
+            // NB: only IPS patches report an unchanged output as `ToOutput`,
+            //     other formats report a source matching their target.
+            let output = match (patch.apply(source), patch) {
+                (Ok(output), _) => output,
+                (Err(Error::ToOutput), AnyPatch::Ips(_)) => source.to_vec(),
+                (Err(error), _) => return Err(error),
+            };
+            let changes = changes(source, &output);
+            outputs.push((output, changes));
+        }
+
+        let mut conflicts = Vec::new();
+        for (i, (output1, changes1)) in outputs.iter().enumerate() {
+            for (j, (output2, changes2)) in outputs.iter().enumerate().skip(i + 1) {
+                for range in intersect(changes1, changes2) {
+                    conflicts.push(Conflict {
+                        values: [slice(output1, &range), slice(output2, &range)],
+                        patches: [i, j],
+                        range,
+                    });
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Check whether both patches write the same bytes over the range.
+    ///
+    /// Such conflicts are harmless, since the patches can be applied in
+    /// any order.
+    pub fn is_benign(&self) -> bool {
+        self.values[0] == self.values[1]
+    }
+}
+
+/// Get the ranges of bytes where `output` differs from `source`.
+fn changes(source: &[u8], output: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in 0..source.len().max(output.len()) {
+        if source.get(i) != output.get(i) {
+            match ranges.last_mut() {
+                Some(range) if range.end == i => range.end += 1,
+                _ => ranges.push(i..i + 1),
+            }
+        }
+    }
+    ranges
+}
+
+/// Get the intersections of two sorted lists of disjoint ranges.
+fn intersect(ranges1: &[Range<usize>], ranges2: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut intersections = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < ranges1.len() && j < ranges2.len() {
+        let start = ranges1[i].start.max(ranges2[j].start);
+        let end = ranges1[i].end.min(ranges2[j].end);
+        if start < end {
+            intersections.push(start..end);
+        }
+        if ranges1[i].end < ranges2[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    intersections
+}
+
+/// Get the bytes of `output` within `range`, if any.
+fn slice(output: &[u8], range: &Range<usize>) -> Vec<u8> {
+    let len = output.len();
+    output[range.start.min(len)..range.end.min(len)].to_vec()
+}
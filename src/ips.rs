@@ -4,9 +4,38 @@ use crate::Result;
 use crate::Error;
 use crate::FlipsMemory;
 
+mod decoder;
+#[cfg(feature = "std")]
+mod ebp;
+#[cfg(feature = "std")]
+mod encoder;
+mod format;
+#[cfg(feature = "std")]
+mod merge;
+mod records;
+#[cfg(feature = "std")]
+mod segments;
+#[cfg(feature = "std")]
+mod writer;
+
+#[cfg(feature = "std")]
+pub use self::ebp::EbpBuilder;
+#[cfg(feature = "std")]
+pub use self::ebp::EbpMetadata;
+#[cfg(feature = "std")]
+pub use self::ebp::EbpPatch;
+pub use self::format::IpsFormat;
+pub use self::records::IpsRecord;
+pub use self::records::IpsRecords;
+#[cfg(feature = "std")]
+pub use self::writer::IpsWriter;
+
 // ---------------------------------------------------------------------------
 
 /// A patch in the IPS format.
+///
+/// Patches in the [IPS32](./enum.IpsFormat.html#variant.Ips32) format are
+/// supported as well, and detected from their magic bytes.
 #[derive(Clone, Debug, PartialEq)]
 pub struct IpsPatch<B: AsRef<[u8]>> {
     buffer: B,
@@ -27,6 +56,14 @@ impl<B: AsRef<[u8]>> IpsPatch<B> {
     pub fn apply<S: AsRef<[u8]>>(&self, source: S) -> Result<IpsOutput> {
         let slice_p = self.buffer.as_ref();
         let slice_s = source.as_ref();
+        if IpsFormat::detect(slice_p) == Some(IpsFormat::Ips32) {
+            let mut study = flips_sys::ips::ipsstudy::default();
+            if decoder::study(slice_p, &mut study) == flips_sys::ips::ipserror::ips_invalid {
+                return Err(Error::Invalid);
+            }
+            return decoder::apply_study(slice_p, &study, slice_s).map(IpsOutput::from);
+        }
+
         let mut mem_o = flips_sys::mem::default();
 
         let result = unsafe {
@@ -35,27 +72,176 @@ impl<B: AsRef<[u8]>> IpsPatch<B> {
             flips_sys::ips::ips_apply(mem_p, mem_i, &mut mem_o as *mut _)
         };
 
+        // NB: `ips_apply` still allocates the output on some errors, so it
+        //     must be wrapped before the result is checked to be freed.
+        let output = FlipsMemory::new(mem_o);
         match Error::from_ips(result) {
-            None => Ok(IpsOutput::from(FlipsMemory::new(mem_o))),
+            None => Ok(IpsOutput::from(output)),
             Some(error) => Err(error),
         }
     }
 
+    /// Apply the patch to a buffer in place, and return the length of the output.
+    ///
+    /// The output is written at the start of `buffer`. Patches extending
+    /// their output past the end of `buffer` cannot be applied in place,
+    /// while patches truncating their output leave the bytes after the
+    /// returned length untouched.
+    ///
+    /// # Error
+    /// Errors are reported like in [`IpsStudy::apply_in_place`].
+    ///
+    /// [`IpsStudy::apply_in_place`]: ./struct.IpsStudy.html#method.apply_in_place
+    pub fn apply_in_place(&self, buffer: &mut [u8]) -> Result<usize> {
+        IpsPatch::new(self.buffer.as_ref()).study()?.apply_in_place(buffer)
+    }
+
+    /// Apply the patch to a vector in place, resizing it to the output length.
+    ///
+    /// # Error
+    /// Errors are reported like in [`IpsStudy::apply_to_vec`].
+    ///
+    /// [`IpsStudy::apply_to_vec`]: ./struct.IpsStudy.html#method.apply_to_vec
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn apply_to_vec(&self, buffer: &mut Vec<u8>) -> Result<()> {
+        IpsPatch::new(self.buffer.as_ref()).study()?.apply_to_vec(buffer)
+    }
+
+    /// Get the format of the patch, detected from its magic bytes.
+    ///
+    /// # Error
+    /// If the patch does not start with the IPS or IPS32 magic bytes, this
+    /// method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn format(&self) -> Result<IpsFormat> {
+        IpsFormat::detect(self.buffer.as_ref()).ok_or(Error::Invalid)
+    }
+
+    /// Iterate over the records of the patch.
+    ///
+    /// # Error
+    /// If the patch does not start with the IPS or IPS32 magic bytes, this
+    /// method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    /// Errors in the records themselves are reported by the iterator.
+    pub fn records(&self) -> Result<IpsRecords<'_>> {
+        IpsRecords::new(self.buffer.as_ref())
+    }
+
+    /// Re-encode the patch with as few bytes as possible.
+    ///
+    /// Adjacent and overlapping records are merged, bytes overwritten by
+    /// later records are dropped, and long runs of the same byte become
+    /// RLE records, following the rules used by [`IpsBuilder`]. The new
+    /// patch has the same format and creates the same output as the
+    /// original one for any source.
+    ///
+    /// # Example
+    /// ```rust
+    /// let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02ab\x00\x00\x03\x00\x01cEOF"[..]);
+    /// let optimized = patch.optimize()?;
+    /// assert_eq!(optimized.as_ref(), b"PATCH\x00\x00\x01\x00\x03abcEOF");
+    /// # Ok::<(), flips::Error>(())
+    /// ```
+    ///
+    /// # Error
+    /// Errors are reported like in [`IpsPatch::merge`], which this method
+    /// uses with the patch on its own.
+    ///
+    /// [`IpsBuilder`]: ./struct.IpsBuilder.html
+    /// [`IpsPatch::merge`]: #method.merge
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn optimize(&self) -> Result<IpsPatch<Vec<u8>>> {
+        merge::merge(core::iter::once(self))
+    }
+
     /// Create a study.
+    ///
+    /// # Error
+    /// If the records of the patch are out of order, this method will
+    /// return [`Error::Scrambled`](./enum.Error.html#variant.Scrambled):
+    /// use [`study_lenient`](#method.study_lenient) to study such patches.
     #[must_use]
     pub fn study(self) -> Result<IpsStudy<B>> {
+        let study = self.study_lenient()?;
+        match study.is_scrambled() {
+            true => Err(Error::Scrambled),
+            false => Ok(study),
+        }
+    }
+
+    /// Create a study, even if the records of the patch are out of order.
+    ///
+    /// A patch with records out of order is technically valid, so it can
+    /// still be studied to get its output length bounds: use
+    /// [`IpsStudy::is_scrambled`] to detect it.
+    ///
+    /// [`IpsStudy::is_scrambled`]: ./struct.IpsStudy.html#method.is_scrambled
+    pub fn study_lenient(self) -> Result<IpsStudy<B>> {
+        let (result, study) = self.study_raw();
+        match Error::from_ips(result) {
+            None | Some(Error::Scrambled) => Ok(IpsStudy::new(self, study)),
+            Some(error) => Err(error),
+        }
+    }
+
+    /// Study the patch, and return the study along with the raw result.
+    ///
+    /// The study is filled even when the result is an error, unless the
+    /// patch is invalid.
+    fn study_raw(&self) -> (flips_sys::ips::ipserror, flips_sys::ips::ipsstudy) {
         let slice_p = self.buffer.as_ref();
         let mut study = flips_sys::ips::ipsstudy::default();
 
-        let result = unsafe {
-            let mem_p = flips_sys::mem::new(slice_p.as_ptr() as *mut _, slice_p.len());
-            flips_sys::ips::ips_study(mem_p, &mut study as *mut _)
+        let result = match IpsFormat::detect(slice_p) {
+            Some(IpsFormat::Ips32) => decoder::study(slice_p, &mut study),
+            _ => unsafe {
+                let mem_p = flips_sys::mem::new(slice_p.as_ptr() as *mut _, slice_p.len());
+                flips_sys::ips::ips_study(mem_p, &mut study as *mut _)
+            },
         };
 
-        match Error::from_ips(result) {
-            None => Ok(IpsStudy::new(self, study)),
-            Some(error) => Err(error),
-        }
+        (result, study)
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[cfg(feature = "std")]
+impl IpsPatch<Vec<u8>> {
+    /// Merge several patches into a single patch with the same effect.
+    ///
+    /// The merged patch creates the same output as applying each patch in
+    /// turn, with the bytes written by a patch overriding the bytes written
+    /// by the previous ones, but it does not need the source to be created.
+    /// It is an IPS32 patch if any of the patches is an IPS32 patch.
+    ///
+    /// # Example
+    /// ```rust
+    /// let translation = flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x03abcEOF"[..]);
+    /// let bugfix = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x02xyEOF"[..]);
+    /// let patch = flips::IpsPatch::merge(&[translation, bugfix])?;
+    /// assert_eq!(patch.apply(&b"0123456789"[..])?.as_ref(), b"01abxy6789");
+    /// # Ok::<(), flips::Error>(())
+    /// ```
+    ///
+    /// # Error
+    /// If a patch is malformed or scrambled, its error is returned, and if
+    /// a patch truncates its output before the end of its records, this
+    /// method will return [`Error::NotThis`]. If the output length of the
+    /// patches depends on the source in a way a single patch cannot express,
+    /// which happens when a patch truncates the output without writing up
+    /// to the end of a previous patch, or when a patch truncates the output
+    /// to the offset of the footer marker before another patch extends it,
+    /// this method will return [`Error::Invalid`].
+    ///
+    /// [`Error::NotThis`]: ./enum.Error.html#variant.NotThis
+    /// [`Error::Invalid`]: ./enum.Error.html#variant.Invalid
+    pub fn merge<'p, I, B>(patches: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'p IpsPatch<B>>,
+        B: AsRef<[u8]> + 'p,
+    {
+        merge::merge(patches)
     }
 }
 
@@ -113,16 +299,130 @@ impl<B: AsRef<[u8]>> IpsStudy<B> {
         }
     }
 
+    /// Get the minimum length of the output.
+    ///
+    /// The output always extends up to the last byte written by the
+    /// records of the patch, unless the patch truncates it.
+    pub fn min_output_len(&self) -> usize {
+        self.study.outlen_min as usize
+    }
+
+    /// Get the maximum length of the output, if the patch truncates it.
+    pub fn max_output_len(&self) -> Option<usize> {
+        match self.study.outlen_max {
+            libc::c_uint::MAX => None,
+            len => Some(len as usize),
+        }
+    }
+
+    /// Get the length of the buffer needed to write all the records.
+    ///
+    /// This may be larger than the output if some records are written
+    /// past the length the output is truncated to.
+    pub fn min_buffer_len(&self) -> usize {
+        self.study.outlen_min_mem as usize
+    }
+
+    /// Get the length of the output created from a source of the given length.
+    pub fn output_len(&self, source_len: usize) -> usize {
+        let len = source_len.max(self.min_output_len());
+        self.max_output_len().map_or(len, |max| len.min(max))
+    }
+
+    /// Check whether the patch truncates its output.
+    pub fn truncates(&self) -> bool {
+        self.max_output_len().is_some()
+    }
+
+    /// Check whether the records of the patch are out of order.
+    ///
+    /// Such patches are technically valid, but they are unlikely to be
+    /// created by a patching tool, and may have been corrupted. They can
+    /// only be studied with [`IpsPatch::study_lenient`].
+    ///
+    /// [`IpsPatch::study_lenient`]: ./struct.IpsPatch.html#method.study_lenient
+    pub fn is_scrambled(&self) -> bool {
+        self.study.error == flips_sys::ips::ipserror::ips_scrambled
+    }
+
+    /// Apply the patch to a buffer in place, and return the length of the output.
+    ///
+    /// See [`IpsPatch::apply_in_place`](./struct.IpsPatch.html#method.apply_in_place).
+    ///
+    /// # Error
+    /// If the output is longer than `buffer`, this method will return
+    /// [`Error::TooBig`]. If the patch is scrambled, this method will return
+    /// [`Error::Scrambled`]. In both cases `buffer` is left untouched. If the
+    /// patch does not change `buffer`, this method will return
+    /// [`Error::ToOutput`].
+    ///
+    /// [`Error::TooBig`]: ./enum.Error.html#variant.TooBig
+    /// [`Error::Scrambled`]: ./enum.Error.html#variant.Scrambled
+    /// [`Error::ToOutput`]: ./enum.Error.html#variant.ToOutput
+    pub fn apply_in_place(&self, buffer: &mut [u8]) -> Result<usize> {
+        let len = self.output_len(buffer.len());
+        if len > buffer.len() {
+            return Err(Error::TooBig);
+        } else if self.is_scrambled() {
+            return Err(Error::Scrambled);
+        }
+
+        let changed = decoder::write(self.patch.buffer.as_ref(), buffer)?;
+        if !changed && len == buffer.len() {
+            Err(Error::ToOutput)
+        } else {
+            Ok(len)
+        }
+    }
+
+    /// Apply the patch to a vector in place, resizing it to the output length.
+    ///
+    /// # Error
+    /// If the patch is scrambled, this method will return [`Error::Scrambled`]
+    /// and leave `buffer` untouched. If the patch does not change `buffer`,
+    /// this method will return [`Error::ToOutput`].
+    ///
+    /// [`Error::Scrambled`]: ./enum.Error.html#variant.Scrambled
+    /// [`Error::ToOutput`]: ./enum.Error.html#variant.ToOutput
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn apply_to_vec(&self, buffer: &mut Vec<u8>) -> Result<()> {
+        if self.is_scrambled() {
+            return Err(Error::Scrambled);
+        }
+
+        let original = buffer.len();
+        let len = self.output_len(original);
+        buffer.resize(len, 0);
+        let changed = decoder::write(self.patch.buffer.as_ref(), buffer)?;
+        if !changed && len == original {
+            Err(Error::ToOutput)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply the studied patch to a source.
+    ///
+    /// # Error
+    /// Errors are reported like in [`IpsPatch::apply`]. In particular, if
+    /// the patch is scrambled, this method will return [`Error::Scrambled`].
+    ///
+    /// [`IpsPatch::apply`]: ./struct.IpsPatch.html#method.apply
+    /// [`Error::Scrambled`]: ./enum.Error.html#variant.Scrambled
     #[must_use]
     pub fn apply<S: AsRef<[u8]>>(&self, source: S) -> Result<IpsOutput> {
+        let slice_p = self.patch.buffer.as_ref();
+        let slice_s = source.as_ref();
+        if IpsFormat::detect(slice_p) == Some(IpsFormat::Ips32) {
+            return decoder::apply_study(slice_p, &self.study, slice_s).map(IpsOutput::from);
+        }
+
         // NB: we have to clone the study because `ips_apply_study` may
         //     change the study error if an error specific to the output
         //     is found. Not cloning would cause the error to be stored
         //     and to invalidate all further calls to `apply`.
         let mut study = self.study.clone();
-
-        let slice_p = self.patch.buffer.as_ref();
-        let slice_s = source.as_ref();
         let mut mem_o = flips_sys::mem::default();
 
         let result = unsafe {
@@ -131,8 +431,11 @@ impl<B: AsRef<[u8]>> IpsStudy<B> {
             flips_sys::ips::ips_apply_study(mem_p, &mut study as *mut _, mem_i, &mut mem_o as *mut _)
         };
 
+        // NB: `ips_apply_study` allocates the output before checking it,
+        //     so it must be wrapped before the result is checked to be freed.
+        let output = FlipsMemory::new(mem_o);
         match Error::from_ips(result) {
-            None => Ok(IpsOutput::from(FlipsMemory::new(mem_o))),
+            None => Ok(IpsOutput::from(output)),
             Some(error) => Err(error),
         }
     }
@@ -144,7 +447,9 @@ impl<B: AsRef<[u8]>> IpsStudy<B> {
 #[derive(Clone, Debug, Default)]
 pub struct IpsBuilder<S: AsRef<[u8]>, T: AsRef<[u8]>> {
     source: Option<S>,
-    target: Option<T>
+    target: Option<T>,
+    #[cfg(feature = "std")]
+    format: IpsFormat,
 }
 
 impl<S: AsRef<[u8]>, T: AsRef<[u8]>> IpsBuilder<S, T> {
@@ -153,6 +458,8 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> IpsBuilder<S, T> {
         Self {
             source: None,
             target: None,
+            #[cfg(feature = "std")]
+            format: IpsFormat::Ips,
         }
     }
 
@@ -168,12 +475,28 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> IpsBuilder<S, T> {
         self
     }
 
+    /// Set the format of the patch.
+    ///
+    /// Use [`IpsFormat::Ips32`](./enum.IpsFormat.html#variant.Ips32) to
+    /// create patches for targets larger than the 16 MiB supported by the
+    /// IPS format. Since `libips` does not support IPS32, these patches are
+    /// created by a simpler encoder written in Rust.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn format(&mut self, format: IpsFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
     #[must_use]
     /// Build an IPS patch from `source` to `target`.
     ///
     /// # Error
     /// If either `source` or `target` was not given, this method will
-    /// return [`Error::Canceled`](./enum.Error.html#variant.Canceled).
+    /// return [`Error::Canceled`](./enum.Error.html#variant.Canceled). If
+    /// the buffers are larger than 16 MiB and the format is IPS, this method
+    /// will return [`Error::Partial`](./enum.Error.html#variant.Partial) with
+    /// a patch covering only their first 16 MiB.
     pub fn build(&mut self) -> Result<IpsPatch<FlipsMemory>> {
         if self.source.is_none() || self.target.is_none() {
             return Err(Error::Canceled);
@@ -181,6 +504,16 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> IpsBuilder<S, T> {
 
         let (source, target) = (self.source.take().unwrap(), self.target.take().unwrap());
         let (slice_s, slice_t) = (source.as_ref(), target.as_ref());
+        #[cfg(feature = "std")]
+        {
+            if self.format == IpsFormat::Ips32 {
+                let patch = encoder::create(slice_s, slice_t, self.format)?;
+                let mut mem = FlipsMemory::alloc(patch.as_ref().len())?;
+                mem.as_bytes_mut().copy_from_slice(patch.as_ref());
+                return Ok(IpsPatch::new(mem));
+            }
+        }
+
         let mut mem_patch = flips_sys::mem::default();
 
         let result = unsafe {
@@ -189,10 +522,31 @@ impl<S: AsRef<[u8]>, T: AsRef<[u8]>> IpsBuilder<S, T> {
             flips_sys::ips::ips_create(mem_s, mem_t, &mut mem_patch as *mut _)
         };
 
+        // NB: `ips_create` still creates a patch when the buffers are
+        //     larger than 16 MiB, so it must be freed on errors as well.
+        let mem = FlipsMemory::new(mem_patch);
+        if result == flips_sys::ips::ipserror::ips_16MB {
+            return Err(Self::partial(mem));
+        }
         match Error::from_ips(result) {
-            None => Ok(IpsPatch::new(FlipsMemory::new(mem_patch))),
+            None => Ok(IpsPatch::new(mem)),
             Some(error) => Err(error),
         }
+    }
 
+    /// Get the error reporting a patch created for buffers over 16 MiB.
+    fn partial(mut mem: FlipsMemory) -> Error {
+        // NB: a truncation is meaningless when the target is larger than
+        //     what the patch can address, so it is removed to leave the
+        //     bytes past 16 MiB untouched. The study reports the records
+        //     past the truncation as `ips_notthis`, but is still filled.
+        let patch = IpsPatch::new(mem.as_bytes());
+        let (result, study) = patch.study_raw();
+        let truncates = result != flips_sys::ips::ipserror::ips_invalid && IpsStudy::new(patch, study).truncates();
+        if truncates {
+            let len = mem.len() - IpsFormat::Ips.offset_width();
+            mem.shrink(len);
+        }
+        Error::Partial(IpsPatch::new(mem))
     }
 }
@@ -0,0 +1,115 @@
+//! Study and application of IPS32 patches, which `libips` does not support,
+//! and application of IPS patches to caller-owned buffers.
+//!
+//! This follows the semantics of `ips_study` and `ips_apply_study`, so that
+//! IPS32 patches behave exactly like IPS patches.
+
+use flips_sys::ips::ipserror;
+use flips_sys::ips::ipsstudy;
+
+use crate::Error;
+use crate::FlipsMemory;
+use crate::Result;
+
+use super::IpsRecord;
+use super::IpsRecords;
+
+/// Study a patch, like `ips_study` does.
+///
+/// Patches writing past the 4 GiB that can be reported by a study are
+/// considered invalid.
+pub fn study(patch: &[u8], study: &mut ipsstudy) -> ipserror {
+    study.error = ipserror::ips_invalid;
+    let records = match IpsRecords::new(patch) {
+        Ok(records) => records.unordered(),
+        Err(_) => return ipserror::ips_invalid,
+    };
+
+    let mut error = ipserror::ips_ok;
+    let mut outlen = 0u64;
+    let mut truncate = None;
+    let mut last_offset = 0;
+    for item in records {
+        match item {
+            Err(_) => return ipserror::ips_invalid,
+            Ok(IpsRecord::Truncate { len }) => truncate = Some(len),
+            Ok(record) => {
+                let offset = record.offset().unwrap_or(0);
+                if offset < last_offset {
+                    error = ipserror::ips_scrambled;
+                }
+                last_offset = offset;
+                outlen = outlen.max(u64::from(offset) + record.len() as u64);
+            }
+        }
+    }
+    if outlen > u64::from(libc::c_uint::MAX) {
+        return ipserror::ips_invalid;
+    }
+
+    study.outlen_min_mem = outlen as libc::c_uint;
+    study.outlen_max = libc::c_uint::MAX;
+    if let Some(len) = truncate {
+        study.outlen_max = len;
+        if outlen > u64::from(len) {
+            outlen = u64::from(len);
+            if error == ipserror::ips_ok {
+                error = ipserror::ips_notthis;
+            }
+        }
+    }
+    study.outlen_min = outlen as libc::c_uint;
+    study.error = error.clone();
+    error
+}
+
+/// Apply a studied patch to a source, like `ips_apply_study` does.
+pub fn apply_study(patch: &[u8], study: &ipsstudy, source: &[u8]) -> Result<FlipsMemory> {
+    if study.error == ipserror::ips_invalid {
+        return Err(Error::Invalid);
+    }
+
+    let mut outlen = source.len().max(study.outlen_min as usize);
+    if study.outlen_max != libc::c_uint::MAX {
+        outlen = outlen.min(study.outlen_max as usize);
+    }
+
+    let mut output = FlipsMemory::alloc(outlen.max(study.outlen_min_mem as usize))?;
+    let buffer = output.as_bytes_mut();
+    let n = source.len().min(buffer.len());
+    buffer[..n].copy_from_slice(&source[..n]);
+    write(patch, buffer)?;
+    output.shrink(outlen);
+
+    if output.as_ref() == source {
+        Err(Error::ToOutput)
+    } else {
+        match Error::from_ips(study.error.clone()) {
+            None => Ok(output),
+            Some(error) => Err(error),
+        }
+    }
+}
+
+/// Write the records of a patch to a buffer large enough to hold them.
+///
+/// Returns whether any byte of the buffer was changed.
+pub fn write(patch: &[u8], buffer: &mut [u8]) -> Result<bool> {
+    let mut changed = false;
+    for item in IpsRecords::new(patch)?.unordered() {
+        match item? {
+            IpsRecord::Data { offset, data } => {
+                let target = &mut buffer[offset as usize..][..data.len()];
+                changed |= target != data;
+                target.copy_from_slice(data);
+            }
+            IpsRecord::Rle { offset, len, byte } => {
+                let target = &mut buffer[offset as usize..][..len as usize];
+                changed |= target.iter().any(|&b| b != byte);
+                target.fill(byte);
+            }
+            IpsRecord::Truncate { .. } => (),
+        }
+    }
+    Ok(changed)
+}
@@ -0,0 +1,422 @@
+use core::str;
+
+use crate::text::check_depth;
+use crate::text::fields;
+use crate::text::Cursor;
+use crate::text::Fields;
+use crate::Error;
+use crate::Result;
+
+use super::IpsBuilder;
+use super::IpsOutput;
+use super::IpsPatch;
+use super::IpsRecord;
+use super::IpsRecords;
+
+// ---------------------------------------------------------------------------
+
+/// A patch in the EBP format.
+///
+/// EBP patches, created by the EarthBound Patcher and CoilSnake, are IPS
+/// patches followed by a JSON object describing the patch. The IPS part is
+/// applied like any other IPS patch, and the metadata can be read with
+/// [`metadata`](#method.metadata).
+///
+/// # Example
+/// ```rust
+/// # let bytes = include_bytes!("../../tests/data/patch1to2.ips");
+/// # let mut bytes = bytes.to_vec();
+/// # bytes.extend_from_slice(br#"{"title": "My Hack", "author": "Someone"}"#);
+/// let patch = flips::EbpPatch::new(bytes);
+/// let metadata = patch.metadata().expect("invalid patch").expect("no metadata");
+/// assert_eq!(metadata.title.as_deref(), Some("My Hack"));
+/// assert_eq!(metadata.author.as_deref(), Some("Someone"));
+/// ```
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EbpPatch<B: AsRef<[u8]>> {
+    buffer: B,
+}
+
+impl<B: AsRef<[u8]>> EbpPatch<B> {
+    /// Load a new EBP patch from an arbitrary sequence of bytes.
+    ///
+    /// The patch format is not checked, so this method will always succeed,
+    /// but then [`apply`](#method.apply) or [`metadata`](#method.metadata)
+    /// may fail if the patch can't be read.
+    pub fn new(buffer: B) -> Self {
+        Self { buffer }
+    }
+
+    /// Get the IPS part of the patch.
+    ///
+    /// # Error
+    /// If the IPS part of the patch is malformed, this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn ips(&self) -> Result<IpsPatch<&[u8]>> {
+        self.split().map(|(ips, _)| IpsPatch::new(ips))
+    }
+
+    /// Read the metadata of the patch, if any.
+    ///
+    /// String fields are decoded, other fields are ignored.
+    ///
+    /// # Error
+    /// If the IPS part of the patch is malformed, or if the metadata is not
+    /// a JSON object encoded in UTF-8, this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn metadata(&self) -> Result<Option<EbpMetadata>> {
+        let (_, json) = self.split()?;
+        if json.is_empty() {
+            return Ok(None);
+        }
+        EbpMetadata::parse(json).map(Some)
+    }
+
+    /// Apply the patch to a source.
+    ///
+    /// Only the IPS part is used, the metadata is not checked.
+    pub fn apply<S: AsRef<[u8]>>(&self, source: S) -> Result<IpsOutput> {
+        self.ips()?.apply(source)
+    }
+
+    /// Split the patch in its IPS part and its metadata.
+    fn split(&self) -> Result<(&[u8], &[u8])> {
+        let buffer = self.buffer.as_ref();
+        let records = IpsRecords::new(buffer)?;
+        let width = records.format().offset_width();
+        let end = records.footer_end()?;
+        // NB: IPS patches may store a truncation length after the footer,
+        //     before the metadata, which is a JSON object starting with `{`.
+        match buffer[end..].first() {
+            Some(b'{') | None => Ok(buffer.split_at(end)),
+            Some(_) if buffer.len() - end >= width => Ok(buffer.split_at(end + width)),
+            Some(_) => Ok(buffer.split_at(end)),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> AsRef<[u8]> for EbpPatch<B> {
+    fn as_ref(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// The metadata embedded in an EBP patch.
+///
+/// # Example
+/// ```rust
+/// let mut metadata = flips::EbpMetadata::default();
+/// metadata.title = Some(String::from("My Hack"));
+/// metadata.description = Some(String::from("A \"new\" adventure"));
+/// let json = metadata.to_string();
+/// assert_eq!(flips::EbpMetadata::parse(json.as_bytes()).unwrap(), metadata);
+/// ```
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EbpMetadata {
+    /// The title of the patch.
+    pub title: Option<String>,
+    /// The author of the patch.
+    pub author: Option<String>,
+    /// A description of the patch.
+    pub description: Option<String>,
+    /// Additional string fields, as pairs of keys and values, such as the
+    /// `patcher` field written by CoilSnake.
+    pub extra: Vec<(String, String)>,
+}
+
+impl EbpMetadata {
+    /// Parse metadata from a JSON object encoded in UTF-8.
+    ///
+    /// # Error
+    /// If the bytes are not a JSON object encoded in UTF-8, this method
+    /// will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn parse<B: AsRef<[u8]>>(bytes: B) -> Result<Self> {
+        str::from_utf8(bytes.as_ref())
+            .ok()
+            .and_then(|text| Parser::new(text).document())
+            .ok_or(Error::Invalid)
+    }
+}
+
+impl Fields for EbpMetadata {
+    fn field(&mut self, key: &str) -> Option<&mut Option<String>> {
+        match key {
+            "title" => Some(&mut self.title),
+            "author" => Some(&mut self.author),
+            "description" => Some(&mut self.description),
+            _ => None,
+        }
+    }
+
+    fn extra(&mut self) -> &mut Vec<(String, String)> {
+        &mut self.extra
+    }
+}
+
+impl core::fmt::Display for EbpMetadata {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let known = [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("description", &self.description),
+        ];
+        f.write_str("{")?;
+        for (i, (key, value)) in fields(&known, &self.extra).enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            escape(f, key)?;
+            f.write_str(": ")?;
+            escape(f, value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Write `text` as a JSON string.
+fn escape(f: &mut core::fmt::Formatter, text: &str) -> core::fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+// ---------------------------------------------------------------------------
+
+/// A builder to create an EBP patch.
+///
+/// # Example
+/// ```rust
+/// let mut metadata = flips::EbpMetadata::default();
+/// metadata.title = Some(String::from("My Hack"));
+/// let patch = flips::EbpBuilder::new()
+///     .source(&b"some source bytes"[..])
+///     .target(&b"some target bytes"[..])
+///     .metadata(metadata)
+///     .build()
+///     .expect("could not create patch");
+/// let output = patch.apply(&b"some source bytes"[..]).unwrap();
+/// assert_eq!(output.as_ref(), b"some target bytes");
+/// ```
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, Default)]
+pub struct EbpBuilder<S: AsRef<[u8]>, T: AsRef<[u8]>> {
+    source: Option<S>,
+    target: Option<T>,
+    metadata: Option<EbpMetadata>,
+}
+
+impl<S: AsRef<[u8]>, T: AsRef<[u8]>> EbpBuilder<S, T> {
+    /// Create a new builder for an EBP patch.
+    pub fn new() -> Self {
+        Self {
+            source: None,
+            target: None,
+            metadata: None,
+        }
+    }
+
+    /// Set the source buffer for the patch.
+    pub fn source(&mut self, source: S) -> &mut Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Set the target buffer for the patch.
+    pub fn target(&mut self, target: T) -> &mut Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Set the metadata for the patch, if any.
+    pub fn metadata<M: Into<Option<EbpMetadata>>>(&mut self, metadata: M) -> &mut Self {
+        self.metadata = metadata.into();
+        self
+    }
+
+    /// Build an EBP patch from `source` to `target` with `metadata` if any.
+    ///
+    /// If the target is shorter than the source, the metadata is written
+    /// after the truncation length of the IPS part.
+    ///
+    /// # Error
+    /// Errors are reported like in [`IpsBuilder::build`]. If the first byte
+    /// of the truncation length is `{`, it would be read as the start of
+    /// the metadata, and this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    ///
+    /// [`IpsBuilder::build`]: ./struct.IpsBuilder.html#method.build
+    pub fn build(&mut self) -> Result<EbpPatch<Vec<u8>>> {
+        if self.source.is_none() || self.target.is_none() {
+            return Err(Error::Canceled);
+        }
+
+        let (source, target) = (self.source.take().unwrap(), self.target.take().unwrap());
+        let ips = IpsBuilder::new().source(source).target(target).build()?;
+        if let Some(Ok(IpsRecord::Truncate { len })) = ips.records()?.last() {
+            if len >> 16 == u32::from(b'{') {
+                return Err(Error::Invalid);
+            }
+        }
+
+        let mut patch = ips.as_ref().to_vec();
+        if let Some(metadata) = self.metadata.take() {
+            patch.extend_from_slice(metadata.to_string().as_bytes());
+        }
+        Ok(EbpPatch::new(patch))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// A minimal JSON parser, reading the string fields of an object.
+struct Parser<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { cursor: Cursor::new(text) }
+    }
+
+    /// Consume `prefix`, after any whitespace, if the remaining text starts with it.
+    fn eat(&mut self, prefix: &str) -> bool {
+        self.cursor.skip_whitespace();
+        self.cursor.eat(prefix)
+    }
+
+    /// Read a whole document.
+    fn document(&mut self) -> Option<EbpMetadata> {
+        let mut metadata = EbpMetadata::default();
+        if !self.eat("{") {
+            return None;
+        }
+        if !self.eat("}") {
+            loop {
+                let key = self.string()?;
+                if !self.eat(":") {
+                    return None;
+                }
+                if let Some(value) = self.value(0)? {
+                    metadata.insert(&key, value);
+                }
+                if self.eat("}") {
+                    break;
+                } else if !self.eat(",") {
+                    return None;
+                }
+            }
+        }
+        match self.cursor.text.trim().is_empty() {
+            true => Some(metadata),
+            false => None,
+        }
+    }
+
+    /// Read any value, returning its content if it is a string.
+    fn value(&mut self, depth: usize) -> Option<Option<String>> {
+        check_depth(depth)?;
+        self.cursor.skip_whitespace();
+        if self.cursor.text.starts_with('"') {
+            return self.string().map(Some);
+        }
+
+        let close = if self.eat("{") {
+            "}"
+        } else if self.eat("[") {
+            "]"
+        } else {
+            // numbers and literals are skipped, and checked loosely
+            let skipped = self.cursor.take_until(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)));
+            return match skipped.is_empty() {
+                true => None,
+                false => Some(None),
+            };
+        };
+
+        if !self.eat(close) {
+            loop {
+                if close == "}" {
+                    self.string()?;
+                    if !self.eat(":") {
+                        return None;
+                    }
+                }
+                self.value(depth + 1)?;
+                if self.eat(close) {
+                    break;
+                } else if !self.eat(",") {
+                    return None;
+                }
+            }
+        }
+        Some(None)
+    }
+
+    /// Read a string, replacing its escape sequences.
+    fn string(&mut self) -> Option<String> {
+        if !self.eat("\"") {
+            return None;
+        }
+        let mut output = String::new();
+        let mut chars = self.cursor.text.char_indices();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '"' => {
+                    self.cursor.text = &self.cursor.text[i + 1..];
+                    return Some(output);
+                }
+                '\\' => {
+                    let c = match chars.next()?.1 {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let high = Self::hex(&mut chars)?;
+                            if (0xD800..0xDC00).contains(&high) {
+                                if chars.next()?.1 != '\\' || chars.next()?.1 != 'u' {
+                                    return None;
+                                }
+                                let low = Self::hex(&mut chars)?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return None;
+                                }
+                                core::char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))?
+                            } else {
+                                core::char::from_u32(high)?
+                            }
+                        }
+                        _ => return None,
+                    };
+                    output.push(c);
+                }
+                c if (c as u32) < 0x20 => return None,
+                c => output.push(c),
+            }
+        }
+    }
+
+    /// Read the 4 hexadecimal digits of a `\u` escape sequence.
+    fn hex(chars: &mut str::CharIndices) -> Option<u32> {
+        (0..4).try_fold(0, |n, _| Some((n << 4) | chars.next()?.1.to_digit(16)?))
+    }
+}
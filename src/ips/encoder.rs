@@ -0,0 +1,95 @@
+//! Creation of IPS32 patches, which `libips` does not support.
+
+use core::convert::TryFrom;
+
+use crate::Error;
+use crate::Result;
+
+use super::IpsFormat;
+use super::IpsPatch;
+use super::IpsWriter;
+
+/// Create a patch from `source` to `target` in the given format.
+///
+/// Changed bytes are grouped in records, merging records separated by
+/// fewer unchanged bytes than the size of a record header, and runs of
+/// the same byte long enough to pay for their own records are encoded
+/// as RLE records.
+pub fn create(source: &[u8], target: &[u8], format: IpsFormat) -> Result<IpsPatch<Vec<u8>>> {
+    if source == target {
+        return Err(Error::Identical);
+    }
+    if target.len() as u64 > format.max_offset() {
+        return Err(Error::TooBig);
+    }
+
+    let header = format.offset_width() + 2;
+    let changed = |i: usize| source.get(i) != Some(&target[i]);
+    let mut writer = IpsWriter::with_format(format);
+    let mut i = 0;
+    while i < target.len() {
+        if !changed(i) {
+            i += 1;
+            continue;
+        }
+
+        let mut start = i;
+        let mut end = i + 1;
+        i += 1;
+        while i < target.len() && i - end < header {
+            if changed(i) {
+                end = i + 1;
+            }
+            i += 1;
+        }
+
+        // NB: blocks are separated by at least a record header, so the
+        //     byte before the block can be rewritten to avoid the footer.
+        if start as u64 == u64::from(format.eof_offset()) {
+            start -= 1;
+        }
+        write_block(&mut writer, start, &target[start..end], format)?;
+    }
+
+    if target.len() < source.len() {
+        writer.truncate(u32::try_from(target.len()).map_err(|_| Error::TooBig)?)?;
+    }
+    Ok(writer.finish())
+}
+
+/// Write a block of changed bytes starting at `offset`.
+pub fn write_block(writer: &mut IpsWriter, offset: usize, block: &[u8], format: IpsFormat) -> Result<()> {
+    let header = format.offset_width() + 2;
+    let mut data_start = 0;
+    let mut k = 0;
+    while k < block.len() {
+        let run = block[k..].iter().take_while(|&&b| b == block[k]).count();
+        // NB: a run cannot start at the offset of the footer marker, so its
+        //     first byte is left in the data before it, if any.
+        let mut start = k;
+        if start > data_start && (offset + start) as u64 == u64::from(format.eof_offset()) {
+            start += 1;
+        }
+        // a run needs its own record, and splits the data around it in
+        // two records, so it must be longer than the headers it adds
+        let mut min_run = 3;
+        if data_start < start {
+            min_run += header;
+        }
+        if k + run < block.len() {
+            min_run += header;
+        }
+        if k + run - start > min_run {
+            if data_start < start {
+                writer.data((offset + data_start) as u32, &block[data_start..start])?;
+            }
+            writer.rle((offset + start) as u32, k + run - start, block[k])?;
+            data_start = k + run;
+        }
+        k += run;
+    }
+    if data_start < block.len() {
+        writer.data((offset + data_start) as u32, &block[data_start..])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Low-level helpers to read the IPS binary format.
+
+/// A variant of the IPS format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IpsFormat {
+    /// The original IPS format, with 24-bit offsets addressing up to 16 MiB.
+    #[default]
+    Ips,
+    /// The IPS32 format, with 32-bit offsets addressing up to 4 GiB.
+    ///
+    /// Patches in this format start with `IPS32` and end with `EEOF`.
+    Ips32,
+}
+
+impl IpsFormat {
+    /// Detect the format of a patch from its magic bytes.
+    pub(crate) fn detect(patch: &[u8]) -> Option<Self> {
+        [IpsFormat::Ips, IpsFormat::Ips32]
+            .iter()
+            .cloned()
+            .find(|format| patch.starts_with(format.magic()))
+    }
+
+    /// The magic bytes starting every patch.
+    pub(crate) fn magic(self) -> &'static [u8] {
+        match self {
+            IpsFormat::Ips => b"PATCH",
+            IpsFormat::Ips32 => b"IPS32",
+        }
+    }
+
+    /// The marker ending the records of a patch.
+    #[cfg(feature = "std")]
+    pub(crate) fn footer(self) -> &'static [u8] {
+        match self {
+            IpsFormat::Ips => b"EOF",
+            IpsFormat::Ips32 => b"EEOF",
+        }
+    }
+
+    /// The width of record offsets and truncation lengths, in bytes.
+    pub(crate) fn offset_width(self) -> usize {
+        match self {
+            IpsFormat::Ips => 3,
+            IpsFormat::Ips32 => 4,
+        }
+    }
+
+    /// The record offset that cannot be used, since it reads as the footer.
+    pub(crate) fn eof_offset(self) -> u32 {
+        match self {
+            IpsFormat::Ips => 0x45_4F_46,
+            IpsFormat::Ips32 => 0x45_45_4F_46,
+        }
+    }
+
+    /// The first offset that cannot be stored in a record.
+    #[cfg(feature = "std")]
+    pub(crate) fn max_offset(self) -> u64 {
+        1 << (8 * self.offset_width())
+    }
+}
+
+/// Read a big-endian number of `width` bytes at the given offset in a buffer.
+///
+/// Returns `None` if the buffer is too short.
+pub fn read_be(buffer: &[u8], offset: usize, width: usize) -> Option<u32> {
+    let bytes = buffer.get(offset..offset.checked_add(width)?)?;
+    Some(bytes.iter().fold(0, |n, &b| (n << 8) | u32::from(b)))
+}
+
+/// Write a big-endian number of `width` bytes to a buffer.
+#[cfg(feature = "std")]
+pub fn write_be(buffer: &mut Vec<u8>, n: u32, width: usize) {
+    buffer.extend((0..width).rev().map(|i| (n >> (8 * i)) as u8));
+}
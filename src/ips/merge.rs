@@ -0,0 +1,85 @@
+use crate::Error;
+use crate::Result;
+
+use super::segments::Segment;
+use super::segments::Segments;
+use super::IpsFormat;
+use super::IpsPatch;
+use super::IpsRecord;
+use super::IpsWriter;
+
+/// Clamp a length between the bounds of an IPS output.
+fn clamp(len: u64, min: u64, max: Option<u64>) -> u64 {
+    let len = len.max(min);
+    max.map_or(len, |max| len.min(max))
+}
+
+/// Merge patches into a single patch with the same effect.
+///
+/// The output length of a patch is the source length clamped between the
+/// end of its records and its truncation length, so the output length of
+/// consecutive patches is clamped between bounds computed from the bounds
+/// of each patch. Bytes removed by a truncation and written again by a
+/// later patch are zeroed, like when the patches are applied in turn.
+pub fn merge<'p, I, B>(patches: I) -> Result<IpsPatch<Vec<u8>>>
+where
+    I: IntoIterator<Item = &'p IpsPatch<B>>,
+    B: AsRef<[u8]> + 'p,
+{
+    let mut format = IpsFormat::Ips;
+    let mut segments = Segments::default();
+    let (mut min, mut max) = (0, None);
+
+    for patch in patches {
+        let records = patch.records()?;
+        if records.format() == IpsFormat::Ips32 {
+            format = IpsFormat::Ips32;
+        }
+
+        let mut written = Vec::new();
+        let (mut end, mut truncate) = (0, None);
+        for record in records {
+            match record? {
+                IpsRecord::Truncate { len } => truncate = Some(u64::from(len)),
+                record => written.extend(Segment::from_record(record)),
+            }
+        }
+        for (offset, segment) in &written {
+            end = end.max(offset + segment.len());
+        }
+        if truncate.is_some_and(|len| len < end) {
+            return Err(Error::NotThis);
+        }
+
+        if let Some(previous) = max.filter(|&previous| previous < end) {
+            segments.insert(previous, Segment::Rle(end - previous, 0));
+        }
+        for (offset, segment) in written {
+            segments.insert(offset, segment);
+        }
+        min = clamp(min, end, truncate);
+        max = match max {
+            Some(previous) => Some(clamp(previous, end, truncate)),
+            None => truncate,
+        };
+        if let Some(max) = max {
+            segments.truncate(max);
+        }
+    }
+
+    // NB: the output is at least `min` bytes long, which can only be
+    //     expressed without the source if a record writes up to there.
+    if segments.end() < min {
+        return Err(Error::Invalid);
+    }
+
+    let mut writer = IpsWriter::with_format(format);
+    segments.write(&mut writer, format)?;
+    if let Some(max) = max {
+        if max >= format.max_offset() {
+            return Err(Error::TooBig);
+        }
+        writer.truncate(max as u32)?;
+    }
+    Ok(writer.finish())
+}
@@ -0,0 +1,215 @@
+use core::iter::FusedIterator;
+
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::IpsFormat;
+
+// ---------------------------------------------------------------------------
+
+/// A single record of an IPS patch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpsRecord<'a> {
+    /// Write bytes stored in the patch itself.
+    Data {
+        /// The position of the bytes in the output.
+        offset: u32,
+        /// The bytes to write.
+        data: &'a [u8],
+    },
+    /// Write the same byte several times.
+    Rle {
+        /// The position of the run in the output.
+        offset: u32,
+        /// The number of times the byte is written.
+        len: u16,
+        /// The byte to write.
+        byte: u8,
+    },
+    /// Truncate the output to the given length.
+    ///
+    /// This record can only be the last one of a patch, as it is stored
+    /// after the footer.
+    Truncate {
+        /// The length of the output.
+        len: u32,
+    },
+}
+
+impl<'a> IpsRecord<'a> {
+    /// Get the position in the output written by this record, if any.
+    pub fn offset(&self) -> Option<u32> {
+        match *self {
+            IpsRecord::Data { offset, .. } => Some(offset),
+            IpsRecord::Rle { offset, .. } => Some(offset),
+            IpsRecord::Truncate { .. } => None,
+        }
+    }
+
+    /// Get the number of bytes written to the output by this record.
+    pub fn len(&self) -> usize {
+        match *self {
+            IpsRecord::Data { data, .. } => data.len(),
+            IpsRecord::Rle { len, .. } => len as usize,
+            IpsRecord::Truncate { .. } => 0,
+        }
+    }
+
+    /// Check whether this record writes no bytes to the output.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// An iterator over the records of an IPS patch.
+///
+/// Both the IPS and IPS32 formats are supported, and detected from the
+/// magic bytes of the patch. The iterator stops after the first error.
+/// Records are expected in increasing offset order: a record written before
+/// the previous one is reported as
+/// [`Error::Scrambled`](./enum.Error.html#variant.Scrambled).
+///
+/// # Example
+/// ```rust
+/// # let bytes = include_bytes!("../../tests/data/patch1to2.ips");
+/// let patch = flips::IpsPatch::new(&bytes[..]);
+/// for record in patch.records().expect("invalid header") {
+///     match record.expect("invalid record") {
+///         flips::IpsRecord::Data { offset, data } => println!("{:06x}: {} bytes", offset, data.len()),
+///         flips::IpsRecord::Rle { offset, len, byte } => println!("{:06x}: {} x {:02x}", offset, len, byte),
+///         flips::IpsRecord::Truncate { len } => println!("truncate to {} bytes", len),
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct IpsRecords<'a> {
+    patch: &'a [u8],
+    format: IpsFormat,
+    position: usize,
+    last_offset: u32,
+    ordered: bool,
+    footer: bool,
+    finished: bool,
+}
+
+impl<'a> IpsRecords<'a> {
+    /// Create an iterator over the records of a patch.
+    ///
+    /// # Error
+    /// If the patch does not start with the IPS or IPS32 magic bytes, this
+    /// method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub(crate) fn new(patch: &'a [u8]) -> Result<Self> {
+        let format = IpsFormat::detect(patch).ok_or(Error::Invalid)?;
+        Ok(Self {
+            patch,
+            format,
+            position: format.magic().len(),
+            last_offset: 0,
+            ordered: true,
+            footer: false,
+            finished: false,
+        })
+    }
+
+    /// Get the format of the patch.
+    pub fn format(&self) -> IpsFormat {
+        self.format
+    }
+
+    /// Accept records out of order instead of stopping with an error.
+    pub(crate) fn unordered(mut self) -> Self {
+        self.ordered = false;
+        self
+    }
+
+    /// Read a big-endian number of `width` bytes.
+    fn number(&mut self, width: usize) -> Result<u32> {
+        let n = format::read_be(self.patch, self.position, width).ok_or(Error::Invalid)?;
+        self.position += width;
+        Ok(n)
+    }
+
+    /// Get the position right after the footer marker of the patch.
+    ///
+    /// Anything stored after the footer, such as a truncation length, is not
+    /// checked.
+    #[cfg(feature = "std")]
+    pub(crate) fn footer_end(mut self) -> Result<usize> {
+        self.ordered = false;
+        while !self.footer {
+            self.record()?;
+        }
+        Ok(self.position)
+    }
+
+    /// Read the next record, or `None` if the end of the patch was reached.
+    fn read(&mut self) -> Result<Option<IpsRecord<'a>>> {
+        if !self.footer {
+            if let Some(record) = self.record()? {
+                return Ok(Some(record));
+            }
+        }
+
+        let width = self.format.offset_width();
+        match self.patch.len() - self.position {
+            0 => Ok(None),
+            n if n == width => Ok(Some(IpsRecord::Truncate { len: self.number(width)? })),
+            _ => Err(Error::Invalid),
+        }
+    }
+
+    /// Read the next record before the footer, or `None` after the footer marker.
+    fn record(&mut self) -> Result<Option<IpsRecord<'a>>> {
+        let offset = self.number(self.format.offset_width())?;
+        if offset == self.format.eof_offset() {
+            self.footer = true;
+            return Ok(None);
+        }
+
+        let size = self.number(2)?;
+        let record = if size == 0 {
+            let len = self.number(2)?;
+            let byte = self.number(1)?;
+            if len == 0 {
+                return Err(Error::Invalid);
+            }
+            IpsRecord::Rle { offset, len: len as u16, byte: byte as u8 }
+        } else {
+            let end = self.position + size as usize;
+            let data = self.patch.get(self.position..end).ok_or(Error::Invalid)?;
+            self.position = end;
+            IpsRecord::Data { offset, data }
+        };
+
+        if self.ordered && offset < self.last_offset {
+            return Err(Error::Scrambled);
+        }
+        self.last_offset = offset;
+        Ok(Some(record))
+    }
+}
+
+impl<'a> Iterator for IpsRecords<'a> {
+    type Item = Result<IpsRecord<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.read() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(error) => {
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl<'a> FusedIterator for IpsRecords<'a> {}
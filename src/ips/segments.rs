@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use crate::Error;
+use crate::Result;
+
+use super::encoder;
+use super::IpsFormat;
+use super::IpsRecord;
+use super::IpsWriter;
+
+/// The largest number of bytes a single record can write.
+const MAX_RECORD_LEN: u64 = 0xFFFF;
+
+/// Bytes written to the output by one or more records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// Arbitrary bytes.
+    Data(Vec<u8>),
+    /// The same byte written several times.
+    Rle(u64, u8),
+}
+
+impl Segment {
+    /// Get the offset and the segment written by a record, if any.
+    pub fn from_record(record: IpsRecord) -> Option<(u64, Segment)> {
+        match record {
+            IpsRecord::Data { offset, data } => Some((u64::from(offset), Segment::Data(data.to_vec()))),
+            IpsRecord::Rle { offset, len, byte } => Some((u64::from(offset), Segment::Rle(u64::from(len), byte))),
+            IpsRecord::Truncate { .. } => None,
+        }
+    }
+
+    /// Get the number of bytes written by the segment.
+    pub fn len(&self) -> u64 {
+        match self {
+            Segment::Data(data) => data.len() as u64,
+            Segment::Rle(len, _) => *len,
+        }
+    }
+
+    /// Split the segment in two at the given index, returning the end.
+    fn split_off(&mut self, at: u64) -> Segment {
+        match self {
+            Segment::Data(data) => Segment::Data(data.split_off(at as usize)),
+            Segment::Rle(len, byte) => {
+                let tail = Segment::Rle(*len - at, *byte);
+                *len = at;
+                tail
+            }
+        }
+    }
+}
+
+/// The bytes written by a sequence of records, later records overriding
+/// the bytes written by earlier ones.
+#[derive(Clone, Debug, Default)]
+pub struct Segments {
+    map: BTreeMap<u64, Segment>,
+}
+
+impl Segments {
+    /// Write a segment at the given offset.
+    pub fn insert(&mut self, offset: u64, segment: Segment) {
+        let end = offset + segment.len();
+        if segment.len() == 0 {
+            return;
+        }
+
+        // split the segment overlapping the start, if any
+        if let Some((&start, previous)) = self.map.range_mut(..offset).next_back() {
+            if start + previous.len() > offset {
+                let tail = previous.split_off(offset - start);
+                self.map.insert(offset, tail);
+            }
+        }
+        // remove the segments covered by the new one, keeping the end of the last one
+        let covered = self.map.range(offset..end).map(|(&start, _)| start).collect::<Vec<_>>();
+        for start in covered {
+            let mut removed = self.map.remove(&start).unwrap();
+            if start + removed.len() > end {
+                let tail = removed.split_off(end - start);
+                self.map.insert(end, tail);
+            }
+        }
+        self.map.insert(offset, segment);
+    }
+
+    /// Remove the bytes written at or after the given offset.
+    pub fn truncate(&mut self, offset: u64) {
+        self.map.split_off(&offset);
+        if let Some((&start, last)) = self.map.iter_mut().next_back() {
+            if start + last.len() > offset {
+                last.split_off(offset - start);
+            }
+        }
+    }
+
+    /// Get the offset following the last byte written.
+    pub fn end(&self) -> u64 {
+        self.map.iter().next_back().map_or(0, |(&start, last)| start + last.len())
+    }
+
+    /// Get the contiguous blocks of bytes written by the segments.
+    fn blocks(&self) -> Vec<(u64, Vec<u8>)> {
+        let mut blocks: Vec<(u64, Vec<u8>)> = Vec::new();
+        for (&offset, segment) in &self.map {
+            let block = match blocks.last_mut() {
+                Some((start, block)) if *start + block.len() as u64 == offset => block,
+                _ => {
+                    blocks.push((offset, Vec::new()));
+                    &mut blocks.last_mut().unwrap().1
+                }
+            };
+            match segment {
+                Segment::Data(data) => block.extend_from_slice(data),
+                Segment::Rle(len, byte) => block.resize(block.len() + *len as usize, *byte),
+            }
+        }
+        blocks
+    }
+
+    /// Write the segments with a writer, like `IpsBuilder` would.
+    ///
+    /// Contiguous segments are written as a single block of changed bytes,
+    /// so that adjacent data is merged and long runs become RLE records.
+    ///
+    /// # Error
+    /// If some bytes cannot be addressed by the writer format, this method
+    /// will return [`Error::TooBig`](./enum.Error.html#variant.TooBig). If a
+    /// block starts at the offset of the footer marker, this method will
+    /// return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn write(&self, writer: &mut IpsWriter, format: IpsFormat) -> Result<()> {
+        let max_offset = format.max_offset();
+        for (offset, block) in self.blocks() {
+            // NB: a record starting at the offset of the footer marker must
+            //     be extended back by one byte, but blocks are contiguous, so
+            //     the byte before this one is left to the source and unknown.
+            if offset == u64::from(format.eof_offset()) {
+                return Err(Error::Invalid);
+            }
+            // NB: records must start in the addressable range, so the bytes
+            //     past it are written by a last record starting before it.
+            let end = offset + block.len() as u64;
+            let mut split = end;
+            if end > max_offset {
+                split = end.saturating_sub(MAX_RECORD_LEN).max(offset);
+                if split >= max_offset {
+                    return Err(Error::TooBig);
+                }
+            }
+            let (head, tail) = block.split_at((split - offset) as usize);
+            if !head.is_empty() {
+                encoder::write_block(writer, offset as usize, head, format)?;
+            }
+            if !tail.is_empty() {
+                writer.data(split as u32, tail)?;
+            }
+        }
+        Ok(())
+    }
+}
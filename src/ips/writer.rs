@@ -0,0 +1,279 @@
+use core::convert::TryFrom;
+
+use crate::Error;
+use crate::Result;
+
+use super::format;
+use super::IpsFormat;
+use super::IpsPatch;
+use super::IpsRecord;
+
+/// The largest number of bytes a single record can write.
+const MAX_RECORD_LEN: usize = 0xFFFF;
+
+/// A record buffered by an `IpsWriter`.
+#[derive(Clone, Debug)]
+enum Record {
+    Data { offset: u32, data: Vec<u8> },
+    Rle { offset: u32, len: u16, byte: u8 },
+}
+
+impl Record {
+    /// Get the position of the record in the output.
+    fn offset(&self) -> u32 {
+        match *self {
+            Record::Data { offset, .. } => offset,
+            Record::Rle { offset, .. } => offset,
+        }
+    }
+
+    /// Get the byte written by the record at `offset`, if any.
+    fn byte_at(&self, offset: u32) -> Option<u8> {
+        match self {
+            Record::Data { offset: start, data } => {
+                data.get(offset.checked_sub(*start)? as usize).cloned()
+            }
+            Record::Rle { offset: start, len, byte } => {
+                let i = offset.checked_sub(*start)?;
+                if i < u32::from(*len) {
+                    Some(*byte)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// A writer to assemble an IPS patch from individual records.
+///
+/// Unlike [`IpsBuilder`], this type does not search for differences between
+/// a source and a target, but encodes the records it is given, in the order
+/// they are given. Records longer than the 65535 bytes allowed by the format
+/// are split in several records. Records must be added in increasing offset
+/// order, since `libips` considers other patches to be scrambled.
+///
+/// A record cannot start at offset `0x454F46`, since it would be read as the
+/// `EOF` marker ending the patch. Such records are extended to start one byte
+/// earlier, which is only possible when a previous record writes that byte,
+/// or when it was given with [`eof_byte`](#method.eof_byte). The same goes
+/// for offset `0x45454F46` in the IPS32 format, which reads as the `EEOF`
+/// marker.
+///
+/// # Example
+/// ```rust
+/// let source = b"Hello, world!";
+/// let mut writer = flips::IpsWriter::new();
+/// writer.data(7, b"Rust!")?;
+/// writer.rle(12, 2, b'!')?;
+///
+/// let patch = writer.finish();
+/// let output = patch.apply(&source[..])?;
+/// assert_eq!(output.as_ref(), b"Hello, Rust!!!");
+/// # Ok::<(), flips::Error>(())
+/// ```
+///
+/// [`IpsBuilder`]: ./struct.IpsBuilder.html
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[derive(Clone, Debug, Default)]
+pub struct IpsWriter {
+    format: IpsFormat,
+    records: Vec<Record>,
+    truncate: Option<u32>,
+    eof_byte: Option<u8>,
+}
+
+impl IpsWriter {
+    /// Create a new writer for an IPS patch with no records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new writer for a patch with no records in the given format.
+    pub fn with_format(format: IpsFormat) -> Self {
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    /// Set the output byte right before the offset of the footer marker.
+    ///
+    /// This byte is only used to extend a record starting at the offset of
+    /// the footer marker when no earlier record writes the byte before it,
+    /// so it should be the byte of the output at `0x454F45` (or `0x45454F45`
+    /// for IPS32) once the previous records are applied.
+    pub fn eof_byte(&mut self, byte: u8) -> &mut Self {
+        self.eof_byte = Some(byte);
+        self
+    }
+
+    /// Add a record writing `data` at `offset`.
+    ///
+    /// # Error
+    /// If `data` is empty, if a record would start past the 16 MiB (or 4 GiB
+    /// for IPS32) addressable by the format, or if the record starts at the
+    /// offset of the footer marker and the previous byte is neither written
+    /// by an earlier record nor given with [`eof_byte`](#method.eof_byte),
+    /// this method will return
+    /// [`Error::Invalid`](./enum.Error.html#variant.Invalid). If the record
+    /// starts before the previous one, this method will return
+    /// [`Error::Scrambled`](./enum.Error.html#variant.Scrambled).
+    pub fn data(&mut self, offset: u32, data: &[u8]) -> Result<&mut Self> {
+        if data.is_empty() {
+            return Err(Error::Invalid);
+        }
+
+        let mut records = Vec::new();
+        let (mut offset, mut data) = (offset, data);
+        let mut prefix = self.eof_prefix(offset)?;
+        while !data.is_empty() {
+            let extra = prefix.map_or(0, |_| 1);
+            let mut len = data.len().min(MAX_RECORD_LEN - extra);
+            if len < data.len() && Self::next_offset(offset, len)? == self.format.eof_offset() {
+                len -= 1;
+            }
+            let mut chunk = Vec::with_capacity(extra + len);
+            chunk.extend(prefix.take());
+            chunk.extend_from_slice(&data[..len]);
+            let start = self.check_offset(offset - extra as u32)?;
+            records.push(Record::Data { offset: start, data: chunk });
+            offset = Self::next_offset(offset, len)?;
+            data = &data[len..];
+        }
+
+        self.push(records)
+    }
+
+    /// Add a record writing `byte` repeated `len` times at `offset`.
+    ///
+    /// # Error
+    /// Errors are reported like in [`data`](#method.data).
+    pub fn rle(&mut self, offset: u32, len: usize, byte: u8) -> Result<&mut Self> {
+        if len == 0 {
+            return Err(Error::Invalid);
+        }
+
+        let mut records = Vec::new();
+        let (mut offset, mut len) = (offset, len);
+        if let Some(previous) = self.eof_prefix(offset)? {
+            // NB: the byte before the run can only be merged in the run if
+            //     it is the same byte, otherwise both are written as data.
+            let start = offset - 1;
+            if previous == byte {
+                let n = len.min(MAX_RECORD_LEN - 1);
+                records.push(Record::Rle { offset: start, len: n as u16 + 1, byte });
+                offset = Self::next_offset(offset, n)?;
+                len -= n;
+            } else {
+                records.push(Record::Data { offset: start, data: vec![previous, byte] });
+                offset += 1;
+                len -= 1;
+            }
+        }
+        while len > 0 {
+            let mut n = len.min(MAX_RECORD_LEN);
+            if n < len && Self::next_offset(offset, n)? == self.format.eof_offset() {
+                n -= 1;
+            }
+            let start = self.check_offset(offset)?;
+            records.push(Record::Rle { offset: start, len: n as u16, byte });
+            offset = Self::next_offset(offset, n)?;
+            len -= n;
+        }
+
+        self.push(records)
+    }
+
+    /// Add a record read from another patch.
+    ///
+    /// # Error
+    /// Errors are reported like in [`data`](#method.data) and
+    /// [`truncate`](#method.truncate).
+    pub fn record(&mut self, record: IpsRecord) -> Result<&mut Self> {
+        match record {
+            IpsRecord::Data { offset, data } => self.data(offset, data),
+            IpsRecord::Rle { offset, len, byte } => self.rle(offset, len as usize, byte),
+            IpsRecord::Truncate { len } => self.truncate(len),
+        }
+    }
+
+    /// Truncate the output of the patch to `len` bytes.
+    ///
+    /// # Error
+    /// If `len` cannot be stored in the 24 bits allowed by the format
+    /// (or 32 bits for IPS32),
+    /// this method will return [`Error::Invalid`](./enum.Error.html#variant.Invalid).
+    pub fn truncate(&mut self, len: u32) -> Result<&mut Self> {
+        self.truncate = Some(self.check_offset(len)?);
+        Ok(self)
+    }
+
+    /// Finish the patch.
+    pub fn finish(&self) -> IpsPatch<Vec<u8>> {
+        let width = self.format.offset_width();
+        let mut patch = self.format.magic().to_vec();
+        for record in &self.records {
+            match record {
+                Record::Data { offset, data } => {
+                    format::write_be(&mut patch, *offset, width);
+                    format::write_be(&mut patch, data.len() as u32, 2);
+                    patch.extend_from_slice(data);
+                }
+                Record::Rle { offset, len, byte } => {
+                    format::write_be(&mut patch, *offset, width);
+                    format::write_be(&mut patch, 0, 2);
+                    format::write_be(&mut patch, u32::from(*len), 2);
+                    patch.push(*byte);
+                }
+            }
+        }
+        patch.extend_from_slice(self.format.footer());
+        if let Some(len) = self.truncate {
+            format::write_be(&mut patch, len, width);
+        }
+        IpsPatch::new(patch)
+    }
+
+    /// Add records, checking they do not start before the previous ones.
+    fn push(&mut self, records: Vec<Record>) -> Result<&mut Self> {
+        if let (Some(last), Some(first)) = (self.records.last(), records.first()) {
+            if first.offset() < last.offset() {
+                return Err(Error::Scrambled);
+            }
+        }
+        self.records.extend(records);
+        Ok(self)
+    }
+
+    /// Get the byte to write before a record starting at `offset`, if needed.
+    fn eof_prefix(&self, offset: u32) -> Result<Option<u8>> {
+        if offset != self.format.eof_offset() {
+            return Ok(None);
+        }
+        self.records
+            .iter()
+            .rev()
+            .find_map(|record| record.byte_at(offset - 1))
+            .or(self.eof_byte)
+            .map(Some)
+            .ok_or(Error::Invalid)
+    }
+
+    /// Check an offset can be stored in a record.
+    fn check_offset(&self, offset: u32) -> Result<u32> {
+        if u64::from(offset) < self.format.max_offset() {
+            Ok(offset)
+        } else {
+            Err(Error::Invalid)
+        }
+    }
+
+    /// Get the offset following `len` bytes written at `offset`.
+    fn next_offset(offset: u32, len: usize) -> Result<u32> {
+        u32::try_from(len)
+            .ok()
+            .and_then(|len| offset.checked_add(len))
+            .ok_or(Error::Invalid)
+    }
+}
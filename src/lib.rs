@@ -22,6 +22,19 @@
 //!   and [`Vec<u8>`](https://doc.rust-lang.org/std/vec/struct.Vec.html). Disable to
 //!   compile in `no_std` mode.
 //!
+//! The following features are disabled by default:
+//!
+//! - **`pure-rust`**: apply BPS patches with a decoder written in Rust instead
+//!   of the `libbps` implementation. The output and errors are the same as
+//!   with the C++ backend.
+//! - **`parallel`**: allow creating BPS delta patches on several threads with
+//!   [`rayon`](https://docs.rs/rayon), at the cost of a larger patch.
+//!   Implies `std`.
+//! - **`mmap`**: open files mapped in memory with [`MappedFile`], to create and
+//!   apply BPS patches on large files without reading them first. Implies `std`.
+//!
+//! [`MappedFile`]: ./struct.MappedFile.html
+//!
 //! ## 📋 Changelog
 //!
 //! This project adheres to [Semantic Versioning](http://semver.org/spec/v2.0.0.html)
@@ -37,25 +50,47 @@
 #![cfg_attr(feature = "_doc", feature(doc_cfg, external_doc))]
 #![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate crc32fast;
 #[cfg(feature = "std")]
 extern crate err_derive;
 extern crate flips_sys;
+extern crate libc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 mod ips;
 mod ups;
 mod bps;
+#[cfg(feature = "std")]
+mod conflict;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "std")]
+mod text;
 
 pub use self::bps::*;
 pub use self::ips::*;
 pub use self::ups::*;
+#[cfg(feature = "std")]
+pub use self::conflict::AnyPatch;
+#[cfg(feature = "std")]
+pub use self::conflict::Conflict;
+#[cfg(feature = "mmap")]
+pub use self::mmap::MappedFile;
 
 use core::ops::Deref;
 
 // ---------------------------------------------------------------------------
 
 /// The error type for this crate.
+///
+/// New variants may be added in minor releases, so matching on this type
+/// requires a wildcard arm.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "std", derive(err_derive::Error))]
+#[non_exhaustive]
 pub enum Error {
     /// Attempted to apply a patch not made for the input.
     #[cfg_attr(feature = "std", error(display = "patch is not made for the input"))]
@@ -83,6 +118,37 @@ pub enum Error {
     /// Patch creation was canceled.
     #[cfg_attr(feature = "std", error(display = "patch creation was canceled"))]
     Canceled,
+    /// The patch ends before all its content could be read.
+    #[cfg_attr(feature = "std", error(display = "patch is truncated"))]
+    Truncated,
+    /// The patch does not start with the magic bytes of its format.
+    #[cfg_attr(feature = "std", error(display = "patch does not start with the expected magic bytes"))]
+    BadMagic,
+    /// The checksum of the patch does not match the one stored in the patch.
+    #[cfg_attr(feature = "std", error(display = "patch checksum does not match its content"))]
+    BadChecksum,
+    /// Attempted to create an IPS patch from buffers larger than 16 MiB.
+    ///
+    /// The IPS format cannot address more than 16 MiB, so the patch only
+    /// covers the differences in the first 16 MiB of the buffers. It is
+    /// returned anyway, so that it can either be used as is, or discarded
+    /// in favour of a format without this limit, such as BPS or IPS32.
+    #[cfg_attr(feature = "std", error(display = "buffers are larger than 16 MiB, patch is partial"))]
+    Partial(IpsPatch<FlipsMemory>),
+    /// An I/O error occurred while reading a source or writing an output.
+    ///
+    /// Only methods performing I/O, which require the `std` feature, can
+    /// return this error.
+    #[cfg_attr(feature = "std", error(display = "I/O error: {}", _0))]
+    Io(IoError),
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(IoError::from(e))
+    }
 }
 
 impl Error {
@@ -95,9 +161,10 @@ impl Error {
             ips_thisout => Some(Error::ToOutput),
             ips_scrambled => Some(Error::Scrambled),
             ips_invalid => Some(Error::Invalid),
-            ips_16MB => Some(Error::OutOfMem), // FIXME?
             ips_identical => Some(Error::Identical),
-            ips_shut_up_gcc => unreachable!("{:?} should never be used !", e),
+            // NB: `ips_16MB` is only returned by `ips_create`, along with a
+            //     partial patch, which `IpsBuilder::build` checks for first.
+            ips_16MB | ips_shut_up_gcc => unreachable!("{:?} should never be used !", e),
         }
     }
 
@@ -138,6 +205,62 @@ impl Error {
 /// The result type for this crate.
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// The I/O error carried by [`Error::Io`](./enum.Error.html#variant.Io).
+///
+/// Without the `std` feature, this type cannot be created, but it is still
+/// defined so that the variants of [`Error`](./enum.Error.html) are the
+/// same in every build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IoError {
+    #[cfg(feature = "std")]
+    kind: std::io::ErrorKind,
+    #[cfg(feature = "std")]
+    message: String,
+}
+
+impl IoError {
+    /// Get the kind of the I/O error.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.kind
+    }
+
+    /// Get the message of the I/O error.
+    #[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+    #[cfg(feature = "std")]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[cfg(feature = "std")]
+impl From<std::io::Error> for IoError {
+    fn from(error: std::io::Error) -> Self {
+        Self {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[cfg(feature = "std")]
+impl From<std::io::ErrorKind> for IoError {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        Self::from(std::io::Error::from(kind))
+    }
+}
+
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "std")))]
+#[cfg(feature = "std")]
+impl std::fmt::Display for IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 // ---------------------------------------------------------------------------
 
 /// A slice of memory owned by `flips`.
@@ -155,6 +278,28 @@ impl FlipsMemory {
         Self { mem }
     }
 
+    /// Allocate a new zeroed slice of `len` bytes with the `flips` allocator.
+    fn alloc(len: usize) -> Result<Self> {
+        // NB: `calloc(0, 1)` may return `NULL`, so always request at least
+        //     one byte to distinguish empty buffers from allocation failures.
+        let ptr = unsafe { libc::calloc(len.max(1), 1) as *mut u8 };
+        if ptr.is_null() {
+            Err(Error::OutOfMem)
+        } else {
+            Ok(Self::new(flips_sys::mem::new(ptr, len)))
+        }
+    }
+
+    /// View the memory buffer as a mutable slice of bytes.
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.mem.ptr, self.mem.len) }
+    }
+
+    /// Shrink the slice to its first `len` bytes, without reallocating.
+    fn shrink(&mut self, len: usize) {
+        self.mem.len = self.mem.len.min(len);
+    }
+
     /// View the memory buffer as a raw slice of bytes.
     pub fn as_bytes(&self) -> &[u8] {
         self.mem.as_ref()
@@ -174,6 +319,25 @@ impl AsRef<[u8]> for FlipsMemory {
     }
 }
 
+// NB: the buffer is owned exclusively by `FlipsMemory` and never mutated
+//     through a shared reference, so it can be shared like a `Vec<u8>`.
+unsafe impl Send for FlipsMemory {}
+unsafe impl Sync for FlipsMemory {}
+
+impl Clone for FlipsMemory {
+    fn clone(&self) -> Self {
+        let mut mem = Self::alloc(self.len()).expect("memory allocation failed");
+        mem.as_bytes_mut().copy_from_slice(self.as_bytes());
+        mem
+    }
+}
+
+impl PartialEq for FlipsMemory {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
 impl Deref for FlipsMemory {
     type Target = [u8];
     fn deref(&self) -> &Self::Target {
@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::Result;
+
+/// A read-only file mapped in memory.
+///
+/// This type can be used anywhere a buffer is expected, so that large files
+/// such as disc images can be patched without reading them in memory first.
+/// Pages of the file are only loaded when they are accessed.
+///
+/// # Example
+/// ```rust,no_run
+/// // SAFETY: the files are not modified while they are mapped.
+/// let source = unsafe { flips::MappedFile::open("game.iso")? };
+/// let patch = unsafe { flips::BpsPatch::open("translation.bps")? };
+/// let output = patch.apply(&source)?;
+/// # Ok::<(), flips::Error>(())
+/// ```
+///
+/// # Safety
+/// The mapped bytes are exposed as a `&[u8]`, which Rust assumes never
+/// changes while it is borrowed. If the file is modified or truncated while
+/// it is mapped, by this process or any other, this assumption is broken and
+/// the behaviour is undefined: bytes may change while a patch reads them,
+/// and accessing pages past the new end of the file may crash the process
+/// with `SIGBUS`. This is why the constructors of this type are `unsafe`.
+#[cfg_attr(feature = "_doc", doc(cfg(feature = "mmap")))]
+#[derive(Debug)]
+pub struct MappedFile {
+    // NB: mapping an empty file fails on some platforms, so empty files
+    //     are not mapped at all.
+    mmap: Option<memmap2::Mmap>,
+}
+
+impl MappedFile {
+    /// Map the file at the given path in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// the [type documentation](#safety).
+    ///
+    /// # Error
+    /// If the file cannot be opened or mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_file(&File::open(path)?)
+    }
+
+    /// Map an open file in memory.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while it is mapped, see
+    /// the [type documentation](#safety).
+    ///
+    /// # Error
+    /// If the file cannot be mapped, this method will return
+    /// [`Error::Io`](./enum.Error.html#variant.Io).
+    pub unsafe fn from_file(file: &File) -> Result<Self> {
+        if file.metadata()?.len() == 0 {
+            return Ok(Self { mmap: None });
+        }
+        let mmap = memmap2::Mmap::map(file)?;
+        Ok(Self { mmap: Some(mmap) })
+    }
+
+    /// View the mapped file as a slice of bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.mmap {
+            Some(mmap) => mmap.as_ref(),
+            None => &[],
+        }
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        self.as_bytes()
+    }
+}
@@ -0,0 +1,91 @@
+//! Helpers shared by the parsers of the metadata embedded in patches.
+
+/// The maximum nesting depth accepted by the metadata parsers.
+const MAX_DEPTH: usize = 32;
+
+/// Check that a nested value at `depth` can still be read.
+pub fn check_depth(depth: usize) -> Option<()> {
+    match depth > MAX_DEPTH {
+        true => None,
+        false => Some(()),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Metadata made of known string fields and additional named fields.
+pub trait Fields {
+    /// Get the known field named `name`, if any.
+    fn field(&mut self, name: &str) -> Option<&mut Option<String>>;
+
+    /// Get the additional fields, as pairs of names and values.
+    fn extra(&mut self) -> &mut Vec<(String, String)>;
+
+    /// Record a field, in `extra` unless it is the first known field of
+    /// its name.
+    fn insert(&mut self, name: &str, value: String) {
+        if let Some(field) = self.field(name) {
+            if field.is_none() {
+                *field = Some(value);
+                return;
+            }
+        }
+        self.extra().push((name.to_string(), value));
+    }
+}
+
+/// Iterate over the known fields that are set, followed by the extra fields.
+pub fn fields<'a>(
+    known: &'a [(&'a str, &'a Option<String>)],
+    extra: &'a [(String, String)],
+) -> impl Iterator<Item = (&'a str, &'a str)> {
+    known
+        .iter()
+        .filter_map(|&(name, value)| value.as_ref().map(|v| (name, v.as_str())))
+        .chain(extra.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+}
+
+// ---------------------------------------------------------------------------
+
+/// A cursor over the text remaining to parse.
+pub struct Cursor<'a> {
+    pub text: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text }
+    }
+
+    /// Skip the whitespace at the start of the remaining text.
+    pub fn skip_whitespace(&mut self) {
+        self.text = self.text.trim_start();
+    }
+
+    /// Consume `prefix` if the remaining text starts with it.
+    pub fn eat(&mut self, prefix: &str) -> bool {
+        if self.text.starts_with(prefix) {
+            self.text = &self.text[prefix.len()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume everything until after `end`, and return what was before it.
+    pub fn until(&mut self, end: &str) -> Option<&'a str> {
+        let i = self.text.find(end)?;
+        let skipped = &self.text[..i];
+        self.text = &self.text[i + end.len()..];
+        Some(skipped)
+    }
+
+    /// Consume the text until the first character matching `stop`, or
+    /// until the end, and return what was before it.
+    pub fn take_until<F: Fn(char) -> bool>(&mut self, stop: F) -> &'a str {
+        let end = self.text.find(stop).unwrap_or(self.text.len());
+        let taken = &self.text[..end];
+        self.text = &self.text[end..];
+        taken
+    }
+}
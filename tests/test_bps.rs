@@ -1,5 +1,6 @@
 extern crate flips;
 
+use flips::BpsAction;
 use flips::BpsLinearBuilder;
 use flips::BpsDeltaBuilder;
 
@@ -69,6 +70,229 @@ fn test_apply_invalid() {
     assert_eq!(study.unwrap_err(), flips::Error::Invalid);
 }
 
+#[test]
+fn test_apply_lenient_correct() {
+    let (output, warning) = flips::BpsPatch::new(PATCH_1TO2).apply_lenient(DATA1).unwrap();
+    assert_eq!(output.as_ref(), DATA2);
+    assert_eq!(warning, None);
+}
+
+#[test]
+fn test_apply_lenient_not_this() {
+    let (output, warning) = flips::BpsPatch::new(PATCH_1TO2).apply_lenient(DATA3).unwrap();
+    let warning = warning.expect("missing warning");
+    assert!(warning.source_mismatch);
+    assert!(!warning.target_mismatch);
+    assert_eq!(output.as_ref(), DATA2);
+}
+
+#[test]
+fn test_apply_lenient_invalid() {
+    let result = flips::BpsPatch::new(DATA1).apply_lenient(DATA2);
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_info() {
+    let patch = flips::BpsPatch::new(PATCH_1TO2);
+    let info = patch.info().unwrap();
+    assert_eq!(info.source_size, DATA1.len() as u64);
+    assert_eq!(info.target_size, DATA2.len() as u64);
+    assert_eq!(info.metadata, b"");
+    let patch = flips::BpsPatch::new(PATCH_2TO1);
+    let info = patch.info().unwrap();
+    assert_eq!(info.source_size, DATA2.len() as u64);
+    assert_eq!(info.target_size, DATA1.len() as u64);
+}
+
+#[test]
+fn test_info_checksums() {
+    let (patch_1to2, patch_2to1) = (flips::BpsPatch::new(PATCH_1TO2), flips::BpsPatch::new(PATCH_2TO1));
+    let (info_1to2, info_2to1) = (patch_1to2.info().unwrap(), patch_2to1.info().unwrap());
+    assert_eq!(info_1to2.source_crc32, info_2to1.target_crc32);
+    assert_eq!(info_1to2.target_crc32, info_2to1.source_crc32);
+    assert_ne!(info_1to2.source_crc32, info_1to2.target_crc32);
+}
+
+#[test]
+fn test_info_invalid() {
+    let patch = flips::BpsPatch::new(DATA1);
+    assert_eq!(patch.info().unwrap_err(), flips::Error::Invalid);
+    let patch = flips::BpsPatch::new(&PATCH_1TO2[..10]);
+    assert_eq!(patch.info().unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_verify() {
+    assert_eq!(flips::BpsPatch::new(PATCH_1TO2).verify(), Ok(()));
+    assert_eq!(flips::BpsPatch::new(PATCH_2TO1).verify(), Ok(()));
+}
+
+#[test]
+fn test_verify_bad_magic() {
+    let result = flips::BpsPatch::new(DATA1).verify();
+    assert_eq!(result.unwrap_err(), flips::Error::BadMagic);
+    let result = flips::BpsPatch::new(&b"UPS1"[..]).verify();
+    assert_eq!(result.unwrap_err(), flips::Error::BadMagic);
+}
+
+#[test]
+fn test_verify_truncated() {
+    for &len in &[0, 2, 4, 10, 1000, PATCH_1TO2.len() - 1] {
+        let result = flips::BpsPatch::new(&PATCH_1TO2[..len]).verify();
+        assert_eq!(result.unwrap_err(), flips::Error::Truncated, "len = {}", len);
+    }
+}
+
+#[test]
+fn test_verify_bad_checksum() {
+    for &i in &[20, 1000, PATCH_1TO2.len() - 8, PATCH_1TO2.len() - 1] {
+        let mut patch = PATCH_1TO2.to_vec();
+        patch[i] ^= 0xFF;
+        let result = flips::BpsPatch::new(patch).verify();
+        assert_eq!(result.unwrap_err(), flips::Error::BadChecksum, "i = {}", i);
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+fn write_number(buffer: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let x = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buffer.push(0x80 | x);
+            break;
+        }
+        buffer.push(x);
+        n -= 1;
+    }
+}
+
+#[test]
+fn test_verify_invalid() {
+    // a patch with a valid checksum, but no action to write its target
+    let mut patch = b"BPS1\x80\x81\x80".to_vec();
+    patch.extend_from_slice(&[0; 8]);
+    let crc = crc32(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+    let result = flips::BpsPatch::new(patch).verify();
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_large_sizes() {
+    // a patch copying a 5 GiB source, which cannot be described with 32-bit sizes
+    let size = 5 << 30;
+    let mut patch = b"BPS1".to_vec();
+    write_number(&mut patch, size);
+    write_number(&mut patch, size);
+    write_number(&mut patch, 0);
+    write_number(&mut patch, (size - 1) << 2);
+    patch.extend_from_slice(&[0; 8]);
+    let crc = crc32(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+
+    let patch = flips::BpsPatch::new(patch);
+    let info = patch.info().unwrap();
+    assert_eq!(info.source_size, size);
+    assert_eq!(info.target_size, size);
+    let actions = patch.actions().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(actions, vec![(0, BpsAction::SourceRead { len: size })]);
+    assert!(patch.verify().is_ok());
+}
+
+#[test]
+fn test_stats() {
+    let patch = flips::BpsPatch::new(PATCH_1TO2);
+    let stats = patch.stats().unwrap();
+    assert_eq!(stats.patch_size, PATCH_1TO2.len() as u64);
+    assert_eq!(stats.target_size, DATA2.len() as u64);
+    assert_eq!(stats.metadata_size, 0);
+
+    let mut written = 0;
+    let actions = patch.actions().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+    for (_, action) in &actions {
+        written += action.len();
+    }
+    assert_eq!(stats.actions(), actions.len() as u64);
+    let total = stats.source_read.bytes + stats.target_read.bytes + stats.source_copy.bytes + stats.target_copy.bytes;
+    assert_eq!(total, written);
+    assert!(stats.ratio() > 1.0);
+}
+
+#[test]
+fn test_stats_copies() {
+    // read 1 byte, write "a", copy the 3 source bytes, and repeat 2 bytes
+    let mut patch = b"BPS1\x83\x87\x80".to_vec();
+    patch.extend_from_slice(b"\x80\x81a\x8a\x80\x87\x80");
+    patch.extend_from_slice(&[0; 8]);
+    let crc = crc32(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+
+    let stats = flips::BpsPatch::new(patch).stats().unwrap();
+    assert_eq!(stats.source_read, flips::BpsActionStats { count: 1, bytes: 1 });
+    assert_eq!(stats.target_read, flips::BpsActionStats { count: 1, bytes: 1 });
+    assert_eq!(stats.source_copy, flips::BpsActionStats { count: 1, bytes: 3 });
+    assert_eq!(stats.target_copy, flips::BpsActionStats { count: 1, bytes: 2 });
+    assert_eq!(stats.average_copy_len(), 2.5);
+}
+
+#[test]
+fn test_stats_invalid() {
+    let result = flips::BpsPatch::new(&PATCH_1TO2[..PATCH_1TO2.len() / 2]).stats();
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_actions_replay() {
+    let patch = flips::BpsPatch::new(PATCH_1TO2);
+    let mut output = Vec::new();
+    for item in patch.actions().unwrap() {
+        let (position, action) = item.unwrap();
+        assert_eq!(position, output.len() as u64);
+        match action {
+            BpsAction::SourceRead { len } => {
+                output.extend_from_slice(&DATA1[position as usize..(position + len) as usize]);
+            }
+            BpsAction::TargetRead { bytes } => {
+                output.extend_from_slice(bytes);
+            }
+            BpsAction::SourceCopy { offset, len } => {
+                output.extend_from_slice(&DATA1[offset as usize..(offset + len) as usize]);
+            }
+            BpsAction::TargetCopy { offset, len } => {
+                for i in offset..offset + len {
+                    let byte = output[i as usize];
+                    output.push(byte);
+                }
+            }
+        }
+    }
+    assert_eq!(output, DATA2);
+}
+
+#[test]
+fn test_actions_invalid() {
+    let patch = flips::BpsPatch::new(DATA1);
+    assert_eq!(patch.actions().unwrap_err(), flips::Error::Invalid);
+
+    let mut truncated = PATCH_1TO2[..PATCH_1TO2.len() / 2].to_vec();
+    truncated.extend_from_slice(&PATCH_1TO2[PATCH_1TO2.len() - 12..]);
+    let patch = flips::BpsPatch::new(truncated);
+    let result = patch.actions().unwrap().collect::<Result<Vec<_>, _>>();
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+}
+
 mod linear {
     use super::*;
     make_test!(BpsLinearBuilder);
@@ -84,4 +308,718 @@ mod delta {
         let output = patch.apply(DATA1).unwrap();
         assert_eq!(output.as_ref(), DATA2);
     }
+
+    #[test]
+    fn test_create_apply_with_progress() {
+        let mut last = None;
+        let patch = BpsDeltaBuilder::new()
+            .source(DATA1)
+            .target(DATA2)
+            .build_with_progress(|done, total| {
+                assert!(done <= total);
+                last = Some((done, total));
+                true
+            })
+            .unwrap();
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(output.as_ref(), DATA2);
+        assert!(last.is_some());
+    }
+
+    #[test]
+    fn test_create_canceled() {
+        let mut calls = 0;
+        let result = BpsDeltaBuilder::new()
+            .source(DATA1)
+            .target(DATA2)
+            .build_with_progress(|_, _| {
+                calls += 1;
+                false
+            });
+        assert_eq!(result.unwrap_err(), flips::Error::Canceled);
+        assert!(calls >= 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_create_progress_panic() {
+        let mut builder = BpsDeltaBuilder::new();
+        builder.source(DATA1).target(DATA2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            builder.build_with_progress(|_, _| panic!("progress failed"))
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"progress failed"));
+    }
+}
+
+#[cfg(feature = "std")]
+mod builder {
+    use super::*;
+    use flips::BpsBuilder;
+    use flips::BpsOutcome;
+    use flips::BpsStrategy;
+
+    #[test]
+    fn test_create_apply() {
+        let (patch, report) = BpsBuilder::new().source(DATA1).target(DATA2).build().unwrap();
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(output.as_ref(), DATA2);
+
+        let strategies = report.attempts.iter().map(|a| a.strategy).collect::<Vec<_>>();
+        assert_eq!(strategies, vec![BpsStrategy::Linear, BpsStrategy::Delta]);
+        for attempt in &report.attempts {
+            match attempt.outcome {
+                BpsOutcome::Built(size) => assert!(patch.as_ref().len() <= size),
+                ref other => panic!("unexpected outcome: {:?}", other),
+            }
+        }
+        let winner = report.attempts.iter().find(|a| a.strategy == report.winner).unwrap();
+        assert_eq!(winner.outcome, BpsOutcome::Built(patch.as_ref().len()));
+    }
+
+    #[test]
+    fn test_create_more_memory() {
+        let (_, report) = BpsBuilder::new().source(DATA1).target(DATA2).more_memory(true).build().unwrap();
+        assert_eq!(report.attempts.len(), 3);
+        assert_eq!(report.attempts[2].strategy, BpsStrategy::DeltaMoreMemory);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = BpsStrategy::Linear.memory_estimate(DATA1.len(), DATA2.len());
+        let (_, report) = BpsBuilder::new().source(DATA1).target(DATA2).memory_budget(budget).build().unwrap();
+        assert_eq!(report.winner, BpsStrategy::Linear);
+        assert_eq!(report.attempts[1].outcome, BpsOutcome::OverMemory);
+
+        let result = BpsBuilder::new().source(DATA1).target(DATA2).memory_budget(0).build();
+        assert_eq!(result.unwrap_err(), flips::Error::OutOfMem);
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let result = BpsBuilder::new()
+            .source(DATA1)
+            .target(DATA2)
+            .time_budget(std::time::Duration::from_secs(0))
+            .build();
+        assert_eq!(result.unwrap_err(), flips::Error::Canceled);
+    }
+
+    #[test]
+    fn test_create_identical() {
+        let result = BpsBuilder::new().source(DATA1).target(DATA1).build();
+        assert_eq!(result.unwrap_err(), flips::Error::Identical);
+    }
+
+    #[test]
+    fn test_create_missing_arguments() {
+        let result = BpsBuilder::<&[u8], &[u8]>::new().source(DATA1).build();
+        assert_eq!(result.unwrap_err(), flips::Error::Canceled);
+    }
+}
+
+#[cfg(feature = "parallel")]
+mod parallel {
+    use super::*;
+
+    /// Build a source and a target large enough to be split in chunks.
+    fn buffers() -> (Vec<u8>, Vec<u8>) {
+        let blocks = [DATA1, DATA2, DATA3];
+        let source = (0..64).flat_map(|i| blocks[i % 3].iter().cloned()).collect::<Vec<u8>>();
+        let mut target = source.clone();
+        target.splice(1000..1000, DATA3[..4096].iter().cloned());
+        for i in (0..target.len()).step_by(100_000) {
+            target[i] ^= 0xFF;
+        }
+        (source, target)
+    }
+
+    #[test]
+    fn test_create_apply() {
+        let (source, target) = buffers();
+        let patch = BpsDeltaBuilder::new()
+            .source(&source[..])
+            .target(&target[..])
+            .threads(4)
+            .build()
+            .unwrap();
+        let output = patch.apply(&source[..]).unwrap();
+        assert_eq!(output.as_ref(), &target[..]);
+        assert!(patch.verify().is_ok());
+    }
+
+    #[test]
+    fn test_create_size() {
+        let (source, target) = buffers();
+        let single = BpsDeltaBuilder::new().source(&source[..]).target(&target[..]).threads(1).build().unwrap();
+        let multi = BpsDeltaBuilder::new().source(&source[..]).target(&target[..]).threads(4).build().unwrap();
+        assert!(multi.as_ref().len() < 2 * single.as_ref().len());
+    }
+
+    #[test]
+    fn test_create_default_single_thread() {
+        let (source, target) = buffers();
+        let default = BpsDeltaBuilder::new().source(&source[..]).target(&target[..]).build().unwrap();
+        let single = BpsDeltaBuilder::new().source(&source[..]).target(&target[..]).threads(1).build().unwrap();
+        assert_eq!(default.as_ref(), single.as_ref());
+        let derived = BpsDeltaBuilder::<&[u8], &[u8]>::default().source(&source[..]).target(&target[..]).build().unwrap();
+        assert_eq!(derived.as_ref(), single.as_ref());
+    }
+
+    #[test]
+    fn test_create_metadata() {
+        let (source, target) = buffers();
+        let patch = BpsDeltaBuilder::new()
+            .source(&source[..])
+            .target(&target[..])
+            .metadata(&b"metadata"[..])
+            .threads(2)
+            .build()
+            .unwrap();
+        assert_eq!(patch.info().unwrap().metadata, b"metadata");
+        let output = patch.apply(&source[..]).unwrap();
+        assert_eq!(output.as_ref(), &target[..]);
+    }
+
+    #[test]
+    fn test_builder_threads() {
+        let (source, target) = buffers();
+        let (patch, report) = flips::BpsBuilder::new().source(&source[..]).target(&target[..]).threads(4).build().unwrap();
+        assert_eq!(report.attempts.len(), 2);
+        let output = patch.apply(&source[..]).unwrap();
+        assert_eq!(output.as_ref(), &target[..]);
+    }
+
+    #[test]
+    fn test_create_identical() {
+        let (source, _) = buffers();
+        let result = BpsDeltaBuilder::new().source(&source[..]).target(&source[..]).threads(4).build();
+        assert_eq!(result.unwrap_err(), flips::Error::Identical);
+    }
+}
+
+#[cfg(feature = "mmap")]
+mod mmap {
+    use super::*;
+    use flips::BpsPatch;
+    use flips::MappedFile;
+    use std::path::PathBuf;
+
+    /// A file in the temporary folder, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("flips-{}-{}", std::process::id(), name));
+            std::fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn test_apply_file() {
+        let source = TempFile::new("apply-source", DATA1);
+        let patch = TempFile::new("apply-patch", PATCH_1TO2);
+        let output = unsafe { BpsPatch::open(&patch.0).unwrap().apply_file(&source.0).unwrap() };
+        assert_eq!(output.as_ref(), DATA2);
+    }
+
+    #[test]
+    fn test_apply_missing() {
+        let patch = BpsPatch::new(PATCH_1TO2);
+        let result = unsafe { patch.apply_file(std::env::temp_dir().join("flips-missing.bin")) };
+        match result.unwrap_err() {
+            flips::Error::Io(error) => {
+                assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+                assert!(!error.message().is_empty());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_create_files() {
+        let source = TempFile::new("create-source", DATA1);
+        let target = TempFile::new("create-target", DATA2);
+        let patch = unsafe {
+            BpsDeltaBuilder::new()
+                .source_file(&source.0)
+                .unwrap()
+                .target_file(&target.0)
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+        assert_eq!(patch.apply(DATA1).unwrap().as_ref(), DATA2);
+        let patch = unsafe { BpsLinearBuilder::new().source_file(&source.0).unwrap().target(DATA2).build().unwrap() };
+        assert_eq!(patch.apply(DATA1).unwrap().as_ref(), DATA2);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let empty = TempFile::new("empty", b"");
+        let mapped = unsafe { MappedFile::open(&empty.0).unwrap() };
+        assert!(mapped.is_empty());
+        let patch = BpsLinearBuilder::new().source(mapped).target(DATA1).build().unwrap();
+        assert_eq!(patch.apply(b"").unwrap().as_ref(), DATA1);
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(target_pointer_width = "64")]
+    fn test_large_mapped_source() {
+        // a sparse file larger than 4 GiB, with data past the first 4 GiB
+        let size = (1 << 32) + 4096;
+        let source = TempFile::new("large-mapped", b"");
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut f = std::fs::OpenOptions::new().write(true).open(&source.0).unwrap();
+            f.set_len(size).unwrap();
+            f.seek(SeekFrom::Start(size - 6)).unwrap();
+            f.write_all(b"source").unwrap();
+        }
+        let mapped = unsafe { MappedFile::open(&source.0).unwrap() };
+        assert_eq!(mapped.len() as u64, size);
+
+        // a patch copying the bytes past the first 4 GiB of the source
+        let mut writer = flips::BpsWriter::new(size, 6);
+        writer.action(BpsAction::SourceCopy { offset: size - 6, len: 6 }).unwrap();
+        let patch = writer.finish(&mapped).unwrap();
+        assert_eq!(patch.info().unwrap().source_size, size);
+
+        let output = patch.apply(&mapped).unwrap();
+        assert_eq!(output.as_ref(), b"source");
+    }
+
+    #[test]
+    #[ignore]
+    #[cfg(target_pointer_width = "64")]
+    fn test_large_files() {
+        // sparse files larger than 4 GiB, differing only in their last bytes
+        let size = (1 << 32) + 4096;
+        let source = TempFile::new("large-source", b"");
+        let target = TempFile::new("large-target", b"");
+        for (file, tail) in [(&source, b"source"), (&target, b"target")].iter() {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut f = std::fs::OpenOptions::new().write(true).open(&file.0).unwrap();
+            f.set_len(size).unwrap();
+            f.seek(SeekFrom::Start(size - 6)).unwrap();
+            f.write_all(&tail[..]).unwrap();
+        }
+
+        let patch = unsafe {
+            BpsLinearBuilder::new()
+                .source_file(&source.0)
+                .unwrap()
+                .target_file(&target.0)
+                .unwrap()
+                .build()
+                .unwrap()
+        };
+        let info = patch.info().unwrap();
+        assert_eq!(info.source_size, size);
+        assert_eq!(info.target_size, size);
+
+        let output = unsafe { patch.apply_file(&source.0).unwrap() };
+        assert_eq!(output.len() as u64, size);
+        assert_eq!(&output[output.len() - 6..], b"target");
+    }
+}
+
+#[cfg(feature = "std")]
+mod writer {
+    use super::*;
+
+    #[test]
+    fn test_reencode() {
+        let patch = flips::BpsPatch::new(PATCH_1TO2);
+        let info = patch.info().unwrap();
+        let mut writer = flips::BpsWriter::new(info.source_size, info.target_size);
+        for item in patch.actions().unwrap() {
+            writer.action(item.unwrap().1).unwrap();
+        }
+        let rewritten = writer.finish(DATA1).unwrap();
+        assert_eq!(rewritten.as_ref(), PATCH_1TO2);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut writer = flips::BpsWriter::new(DATA1.len() as u64, 300);
+        writer.metadata(&b"some metadata"[..]);
+        writer.action(BpsAction::SourceCopy { offset: 1000, len: 100 }).unwrap();
+        writer.action(BpsAction::TargetRead { bytes: &DATA2[..100] }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 150, len: 100 }).unwrap();
+        let patch = writer.finish(DATA1).unwrap();
+
+        let info = patch.info().unwrap();
+        assert_eq!(info.metadata, b"some metadata");
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(&output[..100], &DATA1[1000..1100]);
+        assert_eq!(&output[100..200], &DATA2[..100]);
+        assert_eq!(&output[200..250], &DATA2[50..100]);
+        assert_eq!(&output[250..300], &DATA2[50..100]);
+    }
+
+    #[test]
+    fn test_invalid_action() {
+        let mut writer = flips::BpsWriter::new(10, 20);
+        let result = writer.action(BpsAction::SourceRead { len: 11 });
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+        let result = writer.action(BpsAction::SourceCopy { offset: 5, len: 6 });
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+        let result = writer.action(BpsAction::TargetCopy { offset: 0, len: 1 });
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+        let result = writer.action(BpsAction::TargetRead { bytes: &[0; 21] });
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+        let result = writer.action(BpsAction::TargetRead { bytes: &[] });
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+        assert_eq!(writer.position(), 0);
+    }
+
+    #[test]
+    fn test_finish_out_of_mem() {
+        let size = 1 << 60;
+        let mut writer = flips::BpsWriter::new(0, size);
+        writer.action(BpsAction::TargetRead { bytes: b"a" }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 0, len: size - 1 }).unwrap();
+        assert_eq!(writer.finish(b"").unwrap_err(), flips::Error::OutOfMem);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let mut writer = flips::BpsWriter::new(10, 20);
+        writer.action(BpsAction::SourceRead { len: 10 }).unwrap();
+        assert_eq!(writer.finish(&[0; 10][..]).unwrap_err(), flips::Error::Invalid);
+        writer.action(BpsAction::TargetCopy { offset: 0, len: 10 }).unwrap();
+        assert_eq!(writer.finish(&[0; 11][..]).unwrap_err(), flips::Error::NotThis);
+        assert!(writer.finish(&[0; 10][..]).is_ok());
+    }
+}
+
+#[cfg(feature = "std")]
+mod stream {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_apply_correct() {
+        let mut target = Cursor::new(Vec::new());
+        let patch = flips::BpsPatch::new(PATCH_1TO2);
+        patch.apply_stream(Cursor::new(DATA1), &mut target).unwrap();
+        assert_eq!(target.into_inner(), DATA2);
+        let mut target = Cursor::new(Vec::new());
+        let patch = flips::BpsPatch::new(PATCH_2TO1);
+        patch.apply_stream(Cursor::new(DATA2), &mut target).unwrap();
+        assert_eq!(target.into_inner(), DATA1);
+    }
+
+    #[test]
+    fn test_apply_copies() {
+        let mut writer = flips::BpsWriter::new(DATA1.len() as u64, 300_000);
+        writer.action(BpsAction::SourceRead { len: 10 }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 7, len: 100_000 }).unwrap();
+        writer.action(BpsAction::SourceCopy { offset: 0, len: 65_536 }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 10, len: 134_454 }).unwrap();
+        let patch = writer.finish(DATA1).unwrap();
+
+        let mut target = Cursor::new(Vec::new());
+        patch.apply_stream(Cursor::new(DATA1), &mut target).unwrap();
+        let output = target.into_inner();
+        assert_eq!(&output[..10], &DATA1[..10]);
+        assert!(output[10..100_010].chunks(3).all(|c| c == &DATA1[7..7 + c.len()]));
+        assert_eq!(&output[100_010..165_546], DATA1);
+        assert_eq!(&output[165_546..], &output[10..134_464]);
+    }
+
+    #[test]
+    fn test_apply_existing_target() {
+        let patch = flips::BpsPatch::new(PATCH_1TO2);
+        let mut target = Cursor::new(vec![0xFF; DATA2.len() / 2]);
+        patch.apply_stream(Cursor::new(DATA1), &mut target).unwrap();
+        assert_eq!(target.into_inner(), DATA2);
+
+        let mut target = Cursor::new(vec![0xFF; DATA2.len() + 10]);
+        let result = patch.apply_stream(Cursor::new(DATA1), &mut target);
+        assert_eq!(result.unwrap_err(), flips::Error::Io(std::io::ErrorKind::InvalidInput.into()));
+        assert_eq!(&target.get_ref()[..DATA2.len()], DATA2);
+    }
+
+    #[test]
+    fn test_apply_to_output() {
+        let patch = flips::BpsPatch::new(PATCH_1TO2);
+        let result = patch.apply_stream(Cursor::new(DATA2), Cursor::new(Vec::new()));
+        assert_eq!(result.unwrap_err(), flips::Error::ToOutput);
+    }
+
+    #[test]
+    fn test_apply_not_this() {
+        let patch = flips::BpsPatch::new(PATCH_1TO2);
+        let result = patch.apply_stream(Cursor::new(DATA3), Cursor::new(Vec::new()));
+        assert_eq!(result.unwrap_err(), flips::Error::NotThis);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let patch = flips::BpsPatch::new(DATA1);
+        let result = patch.apply_stream(Cursor::new(DATA2), Cursor::new(Vec::new()));
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+    }
+}
+
+#[cfg(feature = "std")]
+mod invert {
+    use super::*;
+
+    #[test]
+    fn test_invert() {
+        let inverse = flips::BpsPatch::new(PATCH_1TO2).invert(DATA1).unwrap();
+        let output = inverse.apply(DATA2).unwrap();
+        assert_eq!(output.as_ref(), DATA1);
+        let inverse = flips::BpsPatch::new(PATCH_2TO1).invert(DATA2).unwrap();
+        let output = inverse.apply(DATA1).unwrap();
+        assert_eq!(output.as_ref(), DATA2);
+    }
+
+    #[test]
+    fn test_invert_copies() {
+        let mut writer = flips::BpsWriter::new(DATA1.len() as u64, 70_000);
+        writer.metadata(&b"some metadata"[..]);
+        writer.action(BpsAction::SourceRead { len: 1000 }).unwrap();
+        writer.action(BpsAction::TargetRead { bytes: &DATA2[..500] }).unwrap();
+        writer.action(BpsAction::SourceCopy { offset: 30_000, len: 20_000 }).unwrap();
+        writer.action(BpsAction::SourceCopy { offset: 25_000, len: 10_000 }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 0, len: 38_500 }).unwrap();
+        let patch = writer.finish(DATA1).unwrap();
+        let target = patch.apply(DATA1).unwrap();
+
+        let inverse = patch.invert(DATA1).unwrap();
+        assert_eq!(inverse.info().unwrap().metadata, b"some metadata");
+        assert!(inverse.as_ref().len() < DATA1.len() - 20_000);
+        let output = inverse.apply(target.as_ref()).unwrap();
+        assert_eq!(output.as_ref(), DATA1);
+    }
+
+    #[test]
+    fn test_invert_to_output() {
+        let result = flips::BpsPatch::new(PATCH_1TO2).invert(DATA2);
+        assert_eq!(result.unwrap_err(), flips::Error::ToOutput);
+    }
+
+    #[test]
+    fn test_invert_not_this() {
+        let result = flips::BpsPatch::new(PATCH_1TO2).invert(DATA3);
+        assert_eq!(result.unwrap_err(), flips::Error::NotThis);
+    }
+
+    #[test]
+    fn test_invert_out_of_mem() {
+        // a small patch declaring a target too large to be allocated
+        let size = 1 << 60;
+        let mut patch = b"BPS1".to_vec();
+        write_number(&mut patch, 0);
+        write_number(&mut patch, size);
+        write_number(&mut patch, 0);
+        write_number(&mut patch, 1);
+        patch.push(b'a');
+        write_number(&mut patch, ((size - 2) << 2) | 3);
+        write_number(&mut patch, 0);
+        patch.extend_from_slice(&[0; 8]);
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+
+        let result = flips::BpsPatch::new(patch).invert(b"");
+        assert_eq!(result.unwrap_err(), flips::Error::OutOfMem);
+    }
+}
+
+#[cfg(feature = "std")]
+mod compose {
+    use super::*;
+
+    #[test]
+    fn test_compose() {
+        let patch = flips::BpsPatch::new(PATCH_1TO2).compose(&flips::BpsPatch::new(PATCH_2TO1)).unwrap();
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(output.as_ref(), DATA1);
+        let patch = flips::BpsPatch::new(PATCH_2TO1).compose(&flips::BpsPatch::new(PATCH_1TO2)).unwrap();
+        let output = patch.apply(DATA2).unwrap();
+        assert_eq!(output.as_ref(), DATA2);
+    }
+
+    #[test]
+    fn test_compose_copies() {
+        let mut writer = flips::BpsWriter::new(DATA1.len() as u64, 50_000);
+        writer.metadata(&b"first"[..]);
+        writer.action(BpsAction::SourceCopy { offset: 40_000, len: 10_000 }).unwrap();
+        writer.action(BpsAction::TargetRead { bytes: &DATA2[..5] }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 9_998, len: 19_995 }).unwrap();
+        writer.action(BpsAction::SourceRead { len: 20_000 }).unwrap();
+        let first = writer.finish(DATA1).unwrap();
+        let intermediate = first.apply(DATA1).unwrap();
+
+        let mut writer = flips::BpsWriter::new(50_000, 60_000);
+        writer.metadata(&b"second"[..]);
+        writer.action(BpsAction::SourceCopy { offset: 9_000, len: 20_000 }).unwrap();
+        writer.action(BpsAction::TargetRead { bytes: &DATA2[..100] }).unwrap();
+        writer.action(BpsAction::SourceRead { len: 19_900 }).unwrap();
+        writer.action(BpsAction::TargetCopy { offset: 15_000, len: 10_000 }).unwrap();
+        writer.action(BpsAction::SourceCopy { offset: 10_003, len: 10_000 }).unwrap();
+        let second = writer.finish(intermediate.as_ref()).unwrap();
+        let target = second.apply(intermediate.as_ref()).unwrap();
+
+        let patch = first.compose(&second).unwrap();
+        assert_eq!(patch.info().unwrap().metadata, b"second");
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(output.as_ref(), target.as_ref());
+    }
+
+    #[test]
+    fn test_compose_not_this() {
+        let result = flips::BpsPatch::new(PATCH_1TO2).compose(&flips::BpsPatch::new(PATCH_1TO2));
+        assert_eq!(result.unwrap_err(), flips::Error::NotThis);
+    }
+
+    #[test]
+    fn test_compose_invalid() {
+        let result = flips::BpsPatch::new(PATCH_1TO2).compose(&flips::BpsPatch::new(DATA1));
+        assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+    }
+}
+
+#[cfg(feature = "std")]
+mod metadata {
+    use super::*;
+    use flips::BpsManifest;
+    use flips::BpsMetadata;
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- created by hand -->
+<patch version="1.0">
+  <information>
+    <title>Some &amp; Hack</title>
+    <author>Someone</author>
+    <version>1.2</version>
+  </information>
+  <description><![CDATA[A <great> hack.]]></description>
+  <website>https://example.com/?a=1&#38;b=2</website>
+  <empty/>
+</patch>
+"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = match BpsMetadata::parse(MANIFEST) {
+            BpsMetadata::Manifest(manifest) => manifest,
+            BpsMetadata::Raw(_) => panic!("could not parse manifest"),
+        };
+        assert_eq!(manifest.root, "patch");
+        assert_eq!(manifest.text, None);
+        assert_eq!(manifest.title.as_ref().unwrap(), "Some & Hack");
+        assert_eq!(manifest.author.as_ref().unwrap(), "Someone");
+        assert_eq!(manifest.version.as_ref().unwrap(), "1.2");
+        assert_eq!(manifest.description.as_ref().unwrap(), "A <great> hack.");
+        assert_eq!(manifest.extra, vec![
+            (String::from("website"), String::from("https://example.com/?a=1&b=2")),
+            (String::from("empty"), String::new()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        let metadata = BpsMetadata::parse(DATA1);
+        assert_eq!(metadata, BpsMetadata::Raw(DATA1.to_vec()));
+        let metadata = BpsMetadata::parse("<title>unclosed");
+        assert_eq!(metadata, BpsMetadata::Raw(b"<title>unclosed".to_vec()));
+        let metadata = BpsMetadata::parse("plain text");
+        assert_eq!(metadata, BpsMetadata::Raw(b"plain text".to_vec()));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let manifest = BpsManifest {
+            title: Some(String::from("<Title>")),
+            author: None,
+            version: Some(String::from("v1 & v2")),
+            description: Some(String::from("Multiple\nlines")),
+            extra: vec![(String::from("license"), String::from("CC0"))],
+            ..Default::default()
+        };
+        let bytes = BpsMetadata::from(manifest.clone()).to_bytes();
+        assert_eq!(BpsMetadata::parse(&bytes), BpsMetadata::Manifest(manifest));
+    }
+
+    #[test]
+    fn test_roundtrip_parsed() {
+        let manifests = [
+            MANIFEST,
+            "<manifest><title>  Padded\n</title><title>Again</title></manifest>",
+            "<beat>Some text <author>Someone</author> around &amp; fields</beat>",
+            "<root>only text</root>",
+            "<root/>",
+        ];
+        for text in &manifests {
+            let manifest = match BpsMetadata::parse(text) {
+                BpsMetadata::Manifest(manifest) => manifest,
+                BpsMetadata::Raw(_) => panic!("could not parse manifest: {}", text),
+            };
+            let bytes = BpsMetadata::from(manifest.clone()).to_bytes();
+            assert_eq!(BpsMetadata::parse(&bytes), BpsMetadata::Manifest(manifest), "{}", text);
+        }
+
+        let manifest = match BpsMetadata::parse(manifests[2]) {
+            BpsMetadata::Manifest(manifest) => manifest,
+            BpsMetadata::Raw(_) => unreachable!(),
+        };
+        assert_eq!(manifest.root, "beat");
+        assert_eq!(manifest.text.unwrap(), "Some text  around & fields");
+        assert_eq!(manifest.author.unwrap(), "Someone");
+        let manifest = match BpsMetadata::parse(manifests[1]) {
+            BpsMetadata::Manifest(manifest) => manifest,
+            BpsMetadata::Raw(_) => unreachable!(),
+        };
+        assert_eq!(manifest.title.unwrap(), "  Padded\n");
+        assert_eq!(manifest.text, None);
+    }
+
+    #[test]
+    fn test_roundtrip_normalized() {
+        let manifest = BpsManifest {
+            text: Some(String::from(" \n ")),
+            extra: vec![(String::from("title"), String::from("Extra"))],
+            ..Default::default()
+        };
+        let bytes = BpsMetadata::from(manifest).to_bytes();
+        let parsed = match BpsMetadata::parse(&bytes) {
+            BpsMetadata::Manifest(manifest) => manifest,
+            BpsMetadata::Raw(_) => unreachable!(),
+        };
+        assert_eq!(parsed.text, None);
+        assert_eq!(parsed.title.as_deref(), Some("Extra"));
+        assert_eq!(parsed.extra, vec![]);
+    }
+
+    #[test]
+    fn test_output_metadata() {
+        let mut writer = flips::BpsWriter::new(DATA1.len() as u64, 1);
+        writer.metadata(MANIFEST);
+        writer.action(BpsAction::SourceRead { len: 1 }).unwrap();
+        let patch = writer.finish(DATA1).unwrap();
+        let output = patch.apply(DATA1).unwrap();
+        assert_eq!(output.metadata(), Some(MANIFEST.as_bytes()));
+        match output.parse_metadata() {
+            Some(BpsMetadata::Manifest(manifest)) => assert_eq!(manifest.author.unwrap(), "Someone"),
+            other => panic!("unexpected metadata: {:?}", other),
+        }
+
+        let output = flips::BpsPatch::new(PATCH_1TO2).apply(DATA1).unwrap();
+        assert_eq!(output.metadata(), None);
+        assert_eq!(output.parse_metadata(), None);
+    }
 }
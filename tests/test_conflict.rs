@@ -0,0 +1,118 @@
+#![cfg(feature = "std")]
+
+extern crate flips;
+
+use flips::AnyPatch;
+use flips::Conflict;
+use flips::IpsPatch;
+
+const DATA1: &[u8] = include_bytes!("data/data1.bin");
+const DATA2: &[u8] = include_bytes!("data/data2.bin");
+const DATA3: &[u8] = include_bytes!("data/data3.bin");
+
+const IPS_1TO2: &[u8] = include_bytes!("data/patch1to2.ips");
+const UPS_1TO2: &[u8] = include_bytes!("data/patch1to2.ups");
+const BPS_1TO2: &[u8] = include_bytes!("data/patch1to2.bps");
+
+const SOURCE: &[u8] = b"0123456789";
+
+#[test]
+fn test_overlap() {
+    let patch1 = IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x03abcEOF"[..]);
+    let patch2 = IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x03xyzEOF"[..]);
+    let conflicts = Conflict::find(SOURCE, &[(&patch1).into(), (&patch2).into()]).unwrap();
+    assert_eq!(
+        conflicts,
+        vec![Conflict {
+            range: 4..5,
+            patches: [0, 1],
+            values: [b"c".to_vec(), b"x".to_vec()],
+        }]
+    );
+    assert!(!conflicts[0].is_benign());
+}
+
+#[test]
+fn test_disjoint() {
+    let patch1 = IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x03abcEOF"[..]);
+    let patch2 = IpsPatch::new(&b"PATCH\x00\x00\x05\x00\x03xyzEOF"[..]);
+    let conflicts = Conflict::find(SOURCE, &[(&patch1).into(), (&patch2).into()]).unwrap();
+    assert_eq!(conflicts, vec![]);
+}
+
+#[test]
+fn test_unchanged_bytes() {
+    // the second patch writes `4` over `4`, which is not a change
+    let patch1 = IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x03abcEOF"[..]);
+    let patch2 = IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x03\x34yzEOF"[..]);
+    let conflicts = Conflict::find(SOURCE, &[(&patch1).into(), (&patch2).into()]).unwrap();
+    assert_eq!(conflicts, vec![]);
+}
+
+#[test]
+fn test_several_patches() {
+    let patch1 = IpsPatch::new(&b"PATCH\x00\x00\x00\x00\x05abcdeEOF"[..]);
+    let patch2 = IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x01x\x00\x00\x03\x00\x01xEOF"[..]);
+    let patch3 = IpsPatch::new(&b"PATCH\x00\x00\x03\x00\x00\x00\x03xEOF"[..]);
+    let patches = [AnyPatch::from(&patch1), AnyPatch::from(&patch2), AnyPatch::from(&patch3)];
+    let conflicts = Conflict::find(SOURCE, &patches).unwrap();
+    let found = conflicts.iter().map(|c| (c.patches, c.range.clone())).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![([0, 1], 1..2), ([0, 1], 3..4), ([0, 2], 3..5), ([1, 2], 3..4)]
+    );
+    assert!(conflicts[3].is_benign());
+}
+
+#[test]
+fn test_truncate() {
+    let patch1 = IpsPatch::new(&b"PATCHEOF\x00\x00\x06"[..]);
+    let patch2 = IpsPatch::new(&b"PATCH\x00\x00\x08\x00\x04wxyzEOF"[..]);
+    let conflicts = Conflict::find(SOURCE, &[(&patch1).into(), (&patch2).into()]).unwrap();
+    assert_eq!(
+        conflicts,
+        vec![Conflict {
+            range: 8..10,
+            patches: [0, 1],
+            values: [b"".to_vec(), b"wx".to_vec()],
+        }]
+    );
+}
+
+#[test]
+fn test_unchanged_source() {
+    let patch1 = IpsPatch::new(IPS_1TO2);
+    let patch2 = IpsPatch::new(IPS_1TO2);
+    let conflicts = Conflict::find(DATA2, &[(&patch1).into(), (&patch2).into()]).unwrap();
+    assert_eq!(conflicts, vec![]);
+}
+
+#[test]
+fn test_already_patched() {
+    let ips = IpsPatch::new(IPS_1TO2);
+    let bps = flips::BpsPatch::new(BPS_1TO2);
+    let result = Conflict::find(DATA2, &[(&ips).into(), (&bps).into()]);
+    assert_eq!(result.unwrap_err(), flips::Error::ToOutput);
+}
+
+#[test]
+fn test_formats() {
+    let ips = IpsPatch::new(IPS_1TO2);
+    let ups = flips::UpsPatch::new(UPS_1TO2);
+    let bps = flips::BpsPatch::new(BPS_1TO2);
+    let patches = [AnyPatch::from(&ips), AnyPatch::from(&ups), AnyPatch::from(&bps)];
+    let conflicts = Conflict::find(DATA1, &patches).unwrap();
+    assert!(!conflicts.is_empty());
+    assert!(conflicts.iter().all(Conflict::is_benign));
+    for pair in &[[0, 1], [0, 2], [1, 2]] {
+        assert!(conflicts.iter().any(|c| &c.patches == pair));
+    }
+}
+
+#[test]
+fn test_not_this() {
+    let ips = IpsPatch::new(IPS_1TO2);
+    let bps = flips::BpsPatch::new(BPS_1TO2);
+    let result = Conflict::find(DATA3, &[(&ips).into(), (&bps).into()]);
+    assert_eq!(result.unwrap_err(), flips::Error::NotThis);
+}
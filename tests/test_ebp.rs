@@ -0,0 +1,127 @@
+#![cfg(feature = "std")]
+
+extern crate flips;
+
+const DATA1: &[u8] = include_bytes!("data/data1.bin");
+const DATA2: &[u8] = include_bytes!("data/data2.bin");
+
+const PATCH_1TO2: &[u8] = include_bytes!("data/patch1to2.ips");
+
+const JSON: &[u8] = br#"{"author": "Someone", "description": "Line 1\nLine \"2\"", "title": "My Hack \u00e9\ud83d\ude00", "patcher": "EBPatcher", "version": 2, "tags": ["a", {"b": null}]}"#;
+
+fn ebp(json: &[u8]) -> Vec<u8> {
+    let mut bytes = PATCH_1TO2.to_vec();
+    bytes.extend_from_slice(json);
+    bytes
+}
+
+#[test]
+fn test_apply() {
+    let patch = flips::EbpPatch::new(ebp(JSON));
+    assert_eq!(patch.ips().unwrap().as_ref(), PATCH_1TO2);
+    let output = patch.apply(DATA1).unwrap();
+    assert_eq!(output.as_ref(), DATA2);
+    assert_eq!(patch.apply(DATA2).unwrap_err(), flips::Error::ToOutput);
+}
+
+#[test]
+fn test_metadata() {
+    let patch = flips::EbpPatch::new(ebp(JSON));
+    let metadata = patch.metadata().unwrap().unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("My Hack \u{e9}\u{1F600}"));
+    assert_eq!(metadata.author.as_deref(), Some("Someone"));
+    assert_eq!(metadata.description.as_deref(), Some("Line 1\nLine \"2\""));
+    assert_eq!(metadata.extra, vec![(String::from("patcher"), String::from("EBPatcher"))]);
+}
+
+#[test]
+fn test_metadata_missing() {
+    let patch = flips::EbpPatch::new(PATCH_1TO2);
+    assert_eq!(patch.metadata().unwrap(), None);
+    assert_eq!(patch.apply(DATA1).unwrap().as_ref(), DATA2);
+
+    // a truncation length is part of the IPS patch, not metadata
+    let patch = flips::EbpPatch::new(&b"PATCH\x00\x00\x01\x00\x01{EOF\x00\x00\x02"[..]);
+    assert_eq!(patch.metadata().unwrap(), None);
+    assert_eq!(patch.apply(&b"0123"[..]).unwrap().as_ref(), b"0{");
+}
+
+#[test]
+fn test_metadata_truncate() {
+    let patch = flips::EbpPatch::new(&b"PATCH\x00\x00\x01\x00\x01aEOF\x00\x00\x02{\"title\": \"x\"}"[..]);
+    assert_eq!(patch.metadata().unwrap().unwrap().title.as_deref(), Some("x"));
+    assert_eq!(patch.apply(&b"0123"[..]).unwrap().as_ref(), b"0a");
+
+    // a 3-byte trailer starting with `{` is metadata, not a truncation length
+    let patch = flips::EbpPatch::new(&b"PATCH\x00\x00\x01\x00\x01aEOF{} "[..]);
+    assert_eq!(patch.metadata().unwrap(), Some(flips::EbpMetadata::default()));
+    assert_eq!(patch.apply(&b"0123"[..]).unwrap().as_ref(), b"0a23");
+}
+
+#[test]
+fn test_metadata_invalid() {
+    for json in &[&b"{"[..], b"[]", b"{\"title\": 1,}", b"{\"title\": \"\\x\"}", b"{} {}", b"\xff"] {
+        let patch = flips::EbpPatch::new(ebp(json));
+        assert_eq!(patch.metadata().unwrap_err(), flips::Error::Invalid);
+    }
+    let patch = flips::EbpPatch::new(DATA1);
+    assert_eq!(patch.metadata().unwrap_err(), flips::Error::Invalid);
+    assert_eq!(patch.apply(DATA1).unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_metadata_roundtrip() {
+    let metadata = flips::EbpMetadata {
+        title: Some(String::from("Tab\tand \\ and \u{1}")),
+        extra: vec![(String::from("patcher"), String::from("EBPatcher"))],
+        ..Default::default()
+    };
+    let json = metadata.to_string();
+    assert_eq!(json, r#"{"title": "Tab\tand \\ and \u0001", "patcher": "EBPatcher"}"#);
+    assert_eq!(flips::EbpMetadata::parse(json.as_bytes()).unwrap(), metadata);
+}
+
+#[test]
+fn test_create() {
+    let metadata = flips::EbpMetadata {
+        author: Some(String::from("Someone")),
+        ..Default::default()
+    };
+    let patch = flips::EbpBuilder::new().source(DATA1).target(DATA2).metadata(metadata.clone()).build().unwrap();
+    assert!(patch.as_ref().ends_with(br#"EOF{"author": "Someone"}"#));
+    assert_eq!(patch.metadata().unwrap(), Some(metadata));
+    assert_eq!(patch.apply(DATA1).unwrap().as_ref(), DATA2);
+
+    let patch = flips::EbpBuilder::new().source(DATA1).target(DATA2).build().unwrap();
+    assert_eq!(patch.metadata().unwrap(), None);
+}
+
+#[test]
+fn test_create_truncate() {
+    let metadata = flips::EbpMetadata {
+        title: Some(String::from("My Hack")),
+        ..Default::default()
+    };
+    let source = &b"0123456789"[..];
+    let mut builder = flips::EbpBuilder::new();
+    let patch = builder.source(source).target(&b"0a23"[..]).metadata(metadata.clone()).build().unwrap();
+    assert_eq!(patch.metadata().unwrap(), Some(metadata));
+    assert_eq!(patch.apply(source).unwrap().as_ref(), b"0a23");
+
+    let patch = flips::EbpBuilder::new().source(source).target(&b"0a23"[..]).build().unwrap();
+    assert_eq!(patch.metadata().unwrap(), None);
+    assert_eq!(patch.apply(source).unwrap().as_ref(), b"0a23");
+}
+
+#[test]
+fn test_create_truncate_ambiguous() {
+    let source = vec![0; 0x7B0010];
+    let result = flips::EbpBuilder::new().source(&source[..]).target(&source[..0x7B0000]).build();
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_create_missing_arguments() {
+    let result = flips::EbpBuilder::<&[u8], &[u8]>::new().source(DATA1).build();
+    assert_eq!(result.unwrap_err(), flips::Error::Canceled);
+}
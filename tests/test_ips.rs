@@ -53,6 +53,104 @@ fn test_create_missing_arguments() {
     assert_eq!(result.unwrap_err(), flips::Error::Canceled);
 }
 
+#[test]
+fn test_create_partial() {
+    let source = vec![0; 17 << 20];
+    let mut target = source.clone();
+    target[0x10] = 1;
+    target[(16 << 20) + 5] = 1;
+
+    let result = flips::IpsBuilder::new().source(&source[..]).target(&target[..]).build();
+    let patch = match result.unwrap_err() {
+        flips::Error::Partial(patch) => patch,
+        other => panic!("unexpected error: {:?}", other),
+    };
+    for record in patch.records().unwrap() {
+        assert!(record.unwrap().offset().is_none_or(|offset| offset < 1 << 24));
+    }
+    assert!(patch.as_ref().ends_with(b"EOF"));
+    let output = patch.apply(&source).unwrap();
+    assert_eq!(output.len(), source.len());
+    assert_eq!(&output[..16 << 20], &target[..16 << 20]);
+}
+
+#[test]
+fn test_create_partial_truncate() {
+    let source = vec![0; 18 << 20];
+    let mut target = vec![0; 17 << 20];
+    target[0x10] = 1;
+
+    let result = flips::IpsBuilder::new().source(&source[..]).target(&target[..]).build();
+    let patch = match result.unwrap_err() {
+        flips::Error::Partial(patch) => patch,
+        other => panic!("unexpected error: {:?}", other),
+    };
+    assert!(!patch.clone().study().unwrap().truncates());
+    let output = patch.apply(&source).unwrap();
+    assert_eq!(output.len(), source.len());
+    assert_eq!(&output[..16 << 20], &target[..16 << 20]);
+}
+
+#[test]
+fn test_records_replay() {
+    let mut output = DATA1.to_vec();
+    for record in flips::IpsPatch::new(PATCH_1TO2).records().unwrap() {
+        match record.unwrap() {
+            flips::IpsRecord::Data { offset, data } => {
+                let offset = offset as usize;
+                output[offset..offset + data.len()].copy_from_slice(data);
+            }
+            flips::IpsRecord::Rle { offset, len, byte } => {
+                let offset = offset as usize;
+                output[offset..offset + len as usize].iter_mut().for_each(|b| *b = byte);
+            }
+            flips::IpsRecord::Truncate { len } => output.truncate(len as usize),
+        }
+    }
+    assert_eq!(output, DATA2);
+}
+
+#[test]
+fn test_records() {
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02ab\x00\x00\x04\x00\x00\x00\x03cEOF\x00\x00\x05"[..]);
+    let records = patch.records().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        records,
+        vec![
+            flips::IpsRecord::Data { offset: 1, data: b"ab" },
+            flips::IpsRecord::Rle { offset: 4, len: 3, byte: b'c' },
+            flips::IpsRecord::Truncate { len: 5 },
+        ]
+    );
+}
+
+#[test]
+fn test_records_invalid() {
+    fn error(bytes: &[u8]) -> flips::Error {
+        let patch = flips::IpsPatch::new(bytes);
+        let records = patch.records().unwrap();
+        records.filter_map(Result::err).next().unwrap()
+    }
+
+    let patch = flips::IpsPatch::new(DATA1);
+    assert_eq!(patch.records().unwrap_err(), flips::Error::Invalid);
+    // truncated patch
+    assert_eq!(error(&PATCH_1TO2[..PATCH_1TO2.len() - 10]), flips::Error::Invalid);
+    // empty RLE record
+    assert_eq!(error(b"PATCH\x00\x00\x01\x00\x00\x00\x00\x00EOF"), flips::Error::Invalid);
+    // trailing bytes after the footer
+    assert_eq!(error(b"PATCHEOF\x00\x00"), flips::Error::Invalid);
+}
+
+#[test]
+fn test_records_scrambled() {
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x01a\x00\x00\x01\x00\x01bEOF"[..]);
+    let mut records = patch.records().unwrap();
+    assert_eq!(records.next().unwrap().unwrap(), flips::IpsRecord::Data { offset: 4, data: b"a" });
+    assert_eq!(records.next().unwrap().unwrap_err(), flips::Error::Scrambled);
+    assert!(records.next().is_none());
+}
+
 #[test]
 fn test_study_apply_correct() {
     let study = flips::IpsPatch::new(PATCH_1TO2).study().unwrap();
@@ -75,3 +173,719 @@ fn test_study_invalid() {
     let study = flips::IpsPatch::new(DATA1).study();
     assert_eq!(study.unwrap_err(), flips::Error::Invalid);
 }
+
+#[test]
+fn test_study_output_len() {
+    let study = flips::IpsPatch::new(PATCH_1TO2).study().unwrap();
+    assert!(study.min_output_len() <= DATA2.len());
+    assert_eq!(study.min_buffer_len(), study.min_output_len());
+    assert_eq!(study.max_output_len(), None);
+    assert_eq!(study.output_len(DATA1.len()), DATA2.len());
+    assert!(!study.truncates());
+    assert!(!study.is_scrambled());
+}
+
+#[test]
+fn test_study_truncate() {
+    // write 4 bytes at offset 2, and truncate the output to 4 bytes
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x04abcdEOF\x00\x00\x04"[..]);
+    let study = patch.study();
+    assert_eq!(study.unwrap_err(), flips::Error::NotThis);
+
+    // write 2 bytes at offset 2, and truncate the output to 6 bytes
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x02abEOF\x00\x00\x06"[..]);
+    let study = patch.study().unwrap();
+    assert_eq!(study.min_output_len(), 4);
+    assert_eq!(study.max_output_len(), Some(6));
+    assert_eq!(study.min_buffer_len(), 4);
+    assert_eq!(study.output_len(2), 4);
+    assert_eq!(study.output_len(10), 6);
+    assert!(study.truncates());
+    assert_eq!(study.apply(&b"0123456789"[..]).unwrap().as_ref(), b"01ab45");
+}
+
+#[test]
+fn test_study_scrambled() {
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x01a\x00\x00\x01\x00\x01bEOF"[..]);
+    assert_eq!(patch.clone().study().unwrap_err(), flips::Error::Scrambled);
+    let study = patch.study_lenient().unwrap();
+    assert!(study.is_scrambled());
+    assert_eq!(study.min_output_len(), 5);
+    assert_eq!(study.apply(&b"0123456789"[..]).unwrap_err(), flips::Error::Scrambled);
+}
+
+#[test]
+fn test_apply_in_place() {
+    let mut buffer = DATA1.to_vec();
+    let len = flips::IpsPatch::new(PATCH_1TO2).apply_in_place(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], DATA2);
+
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02ab\x00\x00\x04\x00\x00\x00\x02cEOF"[..]);
+    let mut buffer = *b"0123456789";
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap(), 10);
+    assert_eq!(&buffer, b"0ab3cc6789");
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap_err(), flips::Error::ToOutput);
+}
+
+#[test]
+fn test_apply_in_place_truncate() {
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02abEOF\x00\x00\x04"[..]);
+    let mut buffer = *b"0123456789";
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap(), 4);
+    assert_eq!(&buffer, b"0ab3456789");
+}
+
+#[test]
+fn test_apply_in_place_too_big() {
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x03\x00\x02abEOF"[..]);
+    let mut buffer = *b"0123";
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap_err(), flips::Error::TooBig);
+    assert_eq!(&buffer, b"0123");
+
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x01a\x00\x00\x01\x00\x01bEOF"[..]);
+    let mut buffer = *b"0123456789";
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap_err(), flips::Error::Scrambled);
+    assert_eq!(&buffer, b"0123456789");
+}
+
+#[test]
+fn test_apply_in_place_invalid() {
+    let mut buffer = DATA2.to_vec();
+    let result = flips::IpsPatch::new(DATA1).apply_in_place(&mut buffer);
+    assert_eq!(result.unwrap_err(), flips::Error::Invalid);
+    let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x04abcdEOF\x00\x00\x04"[..]);
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap_err(), flips::Error::NotThis);
+    assert_eq!(buffer, DATA2);
+}
+
+#[test]
+fn test_ips32_apply_in_place() {
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x01\x00\x02abEEOF"[..]);
+    let mut buffer = *b"0123";
+    assert_eq!(patch.apply_in_place(&mut buffer).unwrap(), 4);
+    assert_eq!(&buffer, b"0ab3");
+}
+
+#[cfg(feature = "std")]
+mod apply_to_vec {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut buffer = DATA1.to_vec();
+        flips::IpsPatch::new(PATCH_1TO2).apply_to_vec(&mut buffer).unwrap();
+        assert_eq!(buffer, DATA2);
+        let result = flips::IpsPatch::new(PATCH_1TO2).apply_to_vec(&mut buffer);
+        assert_eq!(result.unwrap_err(), flips::Error::ToOutput);
+        assert_eq!(buffer, DATA2);
+    }
+
+    #[test]
+    fn test_grow() {
+        let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x03\x00\x02ab\x00\x00\x08\x00\x00\x00\x02cEOF"[..]);
+        let mut buffer = b"0123".to_vec();
+        patch.apply_to_vec(&mut buffer).unwrap();
+        assert_eq!(buffer, b"012ab\x00\x00\x00cc");
+    }
+
+    #[test]
+    fn test_truncate() {
+        let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02abEOF\x00\x00\x04"[..]);
+        let mut buffer = b"0123456789".to_vec();
+        patch.apply_to_vec(&mut buffer).unwrap();
+        assert_eq!(buffer, b"0ab3");
+    }
+
+    #[test]
+    fn test_scrambled() {
+        let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x01a\x00\x00\x01\x00\x01bEOF"[..]);
+        let mut buffer = b"0123456789".to_vec();
+        assert_eq!(patch.apply_to_vec(&mut buffer).unwrap_err(), flips::Error::Scrambled);
+        assert_eq!(buffer, b"0123456789");
+    }
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(flips::IpsPatch::new(PATCH_1TO2).format().unwrap(), flips::IpsFormat::Ips);
+    let patch = flips::IpsPatch::new(&b"IPS32EEOF"[..]);
+    assert_eq!(patch.format().unwrap(), flips::IpsFormat::Ips32);
+    assert_eq!(flips::IpsPatch::new(DATA1).format().unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_ips32_records() {
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x01\x00\x02ab\x00\x00\x00\x04\x00\x00\x00\x03cEEOF\x00\x00\x00\x08"[..]);
+    let records = patch.records().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        records,
+        vec![
+            flips::IpsRecord::Data { offset: 1, data: b"ab" },
+            flips::IpsRecord::Rle { offset: 4, len: 3, byte: b'c' },
+            flips::IpsRecord::Truncate { len: 8 },
+        ]
+    );
+    let output = patch.apply(&b"0123456789"[..]).unwrap();
+    assert_eq!(output.as_ref(), b"0ab3ccc7");
+    let output = patch.apply(&b"01"[..]).unwrap();
+    assert_eq!(output.as_ref(), b"0ab\x00ccc");
+}
+
+#[test]
+fn test_ips32_apply_to_output() {
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x01\x00\x02abEEOF"[..]);
+    assert_eq!(patch.apply(&b"0ab3"[..]).unwrap_err(), flips::Error::ToOutput);
+    let study = patch.study().unwrap();
+    assert_eq!(study.apply(&b"0ab3"[..]).unwrap_err(), flips::Error::ToOutput);
+    assert_eq!(study.apply(&b"0123"[..]).unwrap().as_ref(), b"0ab3");
+}
+
+#[test]
+fn test_ips32_invalid() {
+    // IPS footer in an IPS32 patch
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x01\x00\x02abEOF"[..]);
+    assert_eq!(patch.clone().study().unwrap_err(), flips::Error::Invalid);
+    assert_eq!(patch.apply(&b"0123"[..]).unwrap_err(), flips::Error::Invalid);
+    // 3-byte truncation in an IPS32 patch
+    let patch = flips::IpsPatch::new(&b"IPS32EEOF\x00\x00\x01"[..]);
+    assert_eq!(patch.apply(&b"0123"[..]).unwrap_err(), flips::Error::Invalid);
+}
+
+#[test]
+fn test_ips32_study() {
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x02\x00\x04abcdEEOF\x00\x00\x00\x04"[..]);
+    assert_eq!(patch.study().unwrap_err(), flips::Error::NotThis);
+
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x02\x00\x02abEEOF\x00\x00\x00\x06"[..]);
+    let study = patch.study().unwrap();
+    assert_eq!(study.min_output_len(), 4);
+    assert_eq!(study.max_output_len(), Some(6));
+    assert_eq!(study.output_len(10), 6);
+    assert_eq!(study.apply(&b"0123456789"[..]).unwrap().as_ref(), b"01ab45");
+
+    let patch = flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x04\x00\x01a\x00\x00\x00\x01\x00\x01bEEOF"[..]);
+    assert_eq!(patch.clone().study().unwrap_err(), flips::Error::Scrambled);
+    let study = patch.study_lenient().unwrap();
+    assert!(study.is_scrambled());
+    assert_eq!(study.min_output_len(), 5);
+    assert_eq!(study.apply(&b"0123456789"[..]).unwrap_err(), flips::Error::Scrambled);
+}
+
+#[cfg(feature = "std")]
+mod ips32 {
+    use super::*;
+    use flips::IpsFormat;
+    use flips::IpsRecord;
+    use flips::IpsWriter;
+
+    const EEOF: u32 = 0x45454F46;
+
+    #[test]
+    fn test_create_apply() {
+        let patch = flips::IpsBuilder::new()
+            .source(DATA1)
+            .target(DATA2)
+            .format(IpsFormat::Ips32)
+            .build()
+            .unwrap();
+        assert_eq!(patch.format().unwrap(), IpsFormat::Ips32);
+        assert!(patch.as_ref().ends_with(b"EEOF"));
+        assert_eq!(patch.apply(DATA1).unwrap().as_ref(), DATA2);
+        assert_eq!(patch.apply(DATA2).unwrap_err(), flips::Error::ToOutput);
+
+        let patch = flips::IpsBuilder::new()
+            .source(DATA2)
+            .target(DATA1)
+            .format(IpsFormat::Ips32)
+            .build()
+            .unwrap();
+        assert_eq!(patch.study().unwrap().apply(DATA2).unwrap().as_ref(), DATA1);
+    }
+
+    #[test]
+    fn test_create_identical() {
+        let result = flips::IpsBuilder::new().source(DATA1).target(DATA1).format(IpsFormat::Ips32).build();
+        assert_eq!(result.unwrap_err(), flips::Error::Identical);
+    }
+
+    #[test]
+    fn test_create_large() {
+        let source = vec![0; 17 << 20];
+        let mut target = source.clone();
+        target[0x100] = 1;
+        target[(17 << 20) - 10..].copy_from_slice(b"0123456789");
+        target.extend_from_slice(b"extra");
+
+        let patch = flips::IpsBuilder::new()
+            .source(&source[..])
+            .target(&target[..])
+            .format(IpsFormat::Ips32)
+            .build()
+            .unwrap();
+        let offsets = patch.records().unwrap().map(|r| r.unwrap().offset()).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![Some(0x100), Some((17 << 20) - 10)]);
+        assert_eq!(patch.apply(&source).unwrap().as_ref(), &target[..]);
+    }
+
+    #[test]
+    fn test_create_truncate() {
+        let patch = flips::IpsBuilder::new()
+            .source(&b"0123456789"[..])
+            .target(&b"0a23"[..])
+            .format(IpsFormat::Ips32)
+            .build()
+            .unwrap();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records.last().unwrap(), &IpsRecord::Truncate { len: 4 });
+        assert_eq!(patch.apply(&b"0123456789"[..]).unwrap().as_ref(), b"0a23");
+    }
+
+    #[test]
+    fn test_create_rle() {
+        let source = vec![0; 100];
+        let mut target = source.clone();
+        target[10..12].copy_from_slice(b"ab");
+        target[12..60].iter_mut().for_each(|b| *b = b'z');
+        target[60..62].copy_from_slice(b"cd");
+
+        let patch = flips::IpsBuilder::new()
+            .source(&source[..])
+            .target(&target[..])
+            .format(IpsFormat::Ips32)
+            .build()
+            .unwrap();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                IpsRecord::Data { offset: 10, data: b"ab" },
+                IpsRecord::Rle { offset: 12, len: 48, byte: b'z' },
+                IpsRecord::Data { offset: 60, data: b"cd" },
+            ]
+        );
+        assert_eq!(patch.apply(&source).unwrap().as_ref(), &target[..]);
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = IpsWriter::with_format(IpsFormat::Ips32);
+        writer.data(1 << 24, b"ab").unwrap().truncate(u32::MAX).unwrap();
+        let patch = writer.finish();
+        assert_eq!(patch.as_ref(), &b"IPS32\x01\x00\x00\x00\x00\x02abEEOF\xFF\xFF\xFF\xFF"[..]);
+    }
+
+    #[test]
+    fn test_writer_eof_offset() {
+        let mut writer = IpsWriter::with_format(IpsFormat::Ips32);
+        writer.data(EEOF - 2, b"ab").unwrap().data(EEOF, b"cd").unwrap();
+        let patch = writer.finish();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                IpsRecord::Data { offset: EEOF - 2, data: b"ab" },
+                IpsRecord::Data { offset: EEOF - 1, data: b"bcd" },
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+mod writer {
+    use super::*;
+    use flips::IpsRecord;
+    use flips::IpsWriter;
+
+    const EOF: u32 = 0x454F46;
+
+    #[test]
+    fn test_apply() {
+        let mut writer = IpsWriter::new();
+        writer.data(2, b"abc").unwrap().rle(8, 4, b'z').unwrap();
+        let patch = writer.finish();
+        let output = patch.apply(&b"0123456789"[..]).unwrap();
+        assert_eq!(output.as_ref(), b"01abc567zzzz");
+        assert!(!patch.study().unwrap().is_scrambled());
+    }
+
+    #[test]
+    fn test_reencode() {
+        let mut writer = IpsWriter::new();
+        for record in flips::IpsPatch::new(PATCH_1TO2).records().unwrap() {
+            writer.record(record.unwrap()).unwrap();
+        }
+        assert_eq!(writer.finish().as_ref(), PATCH_1TO2);
+    }
+
+    #[test]
+    fn test_split() {
+        let data = (0..70_000).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut writer = IpsWriter::new();
+        writer.data(0, &data).unwrap().rle(70_000, 70_000, 1).unwrap();
+        let patch = writer.finish();
+
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records.len(), 4);
+        assert!(records.iter().all(|r| r.len() <= 0xFFFF));
+        let output = patch.apply(&[0; 10][..]).unwrap();
+        assert_eq!(&output[..70_000], &data[..]);
+        assert!(output[70_000..].iter().all(|&b| b == 1));
+    }
+
+    #[test]
+    fn test_eof_offset() {
+        let mut writer = IpsWriter::new();
+        writer.data(EOF - 4, b"abcd").unwrap().data(EOF, b"efgh").unwrap();
+        writer.rle(EOF + 4, 2, b'h').unwrap();
+        let patch = writer.finish();
+
+        let offsets = patch.records().unwrap().map(|r| r.unwrap().offset()).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![Some(EOF - 4), Some(EOF - 1), Some(EOF + 4)]);
+        let output = patch.apply(&[0; 16][..]).unwrap();
+        assert_eq!(&output[EOF as usize - 4..], b"abcdefghhh");
+    }
+
+    #[test]
+    fn test_eof_offset_rle() {
+        let mut writer = IpsWriter::new();
+        writer.rle(EOF - 2, 2, b'x').unwrap().rle(EOF, 3, b'x').unwrap();
+        let patch = writer.finish();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records[1], IpsRecord::Rle { offset: EOF - 1, len: 4, byte: b'x' });
+
+        let mut writer = IpsWriter::new();
+        writer.rle(EOF - 2, 2, b'x').unwrap().rle(EOF, 3, b'y').unwrap();
+        let patch = writer.finish();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records[1], IpsRecord::Data { offset: EOF - 1, data: b"xy" });
+        assert_eq!(records[2], IpsRecord::Rle { offset: EOF + 1, len: 2, byte: b'y' });
+        let output = patch.apply(&[0; 16][..]).unwrap();
+        assert_eq!(&output[EOF as usize - 2..], b"xxyyy");
+    }
+
+    #[test]
+    fn test_eof_offset_split() {
+        let data = vec![7; 0x20000];
+        let mut writer = IpsWriter::new();
+        writer.data(EOF - 0xFFFF, &data).unwrap();
+        let patch = writer.finish();
+        for record in patch.records().unwrap() {
+            assert_ne!(record.unwrap().offset(), Some(EOF));
+        }
+        let output = patch.apply(&[0; 16][..]).unwrap();
+        assert_eq!(&output[(EOF - 0xFFFF) as usize..], &data[..]);
+    }
+
+    #[test]
+    fn test_eof_offset_unknown() {
+        let mut writer = IpsWriter::new();
+        assert_eq!(writer.data(EOF, b"a").unwrap_err(), flips::Error::Invalid);
+        writer.data(EOF - 2, b"a").unwrap();
+        assert_eq!(writer.rle(EOF, 1, 0).unwrap_err(), flips::Error::Invalid);
+    }
+
+    #[test]
+    fn test_eof_byte() {
+        let mut writer = IpsWriter::new();
+        writer.eof_byte(b'0').data(EOF, b"ab").unwrap();
+        let patch = writer.finish();
+        let records = patch.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records, vec![IpsRecord::Data { offset: EOF - 1, data: b"0ab" }]);
+
+        let mut writer = IpsWriter::new();
+        writer.eof_byte(b'0').data(EOF - 1, b"x").unwrap().rle(EOF, 2, b'y').unwrap();
+        let patch = writer.finish();
+        let output = patch.apply(&[b'0'; 16][..]).unwrap();
+        assert_eq!(&output[EOF as usize - 1..], b"xyy");
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut writer = IpsWriter::new();
+        writer.data(1, b"ab").unwrap().truncate(4).unwrap();
+        let patch = writer.finish();
+        assert_eq!(patch.records().unwrap().last().unwrap().unwrap(), IpsRecord::Truncate { len: 4 });
+        let output = patch.apply(&b"0123456789"[..]).unwrap();
+        assert_eq!(output.as_ref(), b"0ab3");
+    }
+
+    #[test]
+    fn test_invalid_record() {
+        let mut writer = IpsWriter::new();
+        assert_eq!(writer.data(0, b"").unwrap_err(), flips::Error::Invalid);
+        assert_eq!(writer.rle(0, 0, 0).unwrap_err(), flips::Error::Invalid);
+        assert_eq!(writer.data(1 << 24, b"a").unwrap_err(), flips::Error::Invalid);
+        assert_eq!(writer.truncate(1 << 24).unwrap_err(), flips::Error::Invalid);
+        writer.data(4, b"a").unwrap();
+        assert_eq!(writer.data(1, b"b").unwrap_err(), flips::Error::Scrambled);
+    }
+}
+
+#[cfg(feature = "std")]
+mod merge {
+    use super::*;
+
+    fn apply_all(patches: &[flips::IpsPatch<&[u8]>], source: &[u8]) -> Vec<u8> {
+        let mut output = source.to_vec();
+        for patch in patches {
+            match patch.apply(&output) {
+                Ok(patched) => output = patched.as_ref().to_vec(),
+                Err(flips::Error::ToOutput) => (),
+                Err(error) => panic!("{:?}", error),
+            }
+        }
+        output
+    }
+
+    fn check(patches: &[flips::IpsPatch<&[u8]>], sources: &[&[u8]]) {
+        let merged = flips::IpsPatch::merge(patches).unwrap();
+        for source in sources {
+            let expected = apply_all(patches, source);
+            let output = apply_all(&[flips::IpsPatch::new(merged.as_ref())], source);
+            assert_eq!(output, expected, "source: {:?}", source);
+        }
+    }
+
+    #[test]
+    fn test_merge_data() {
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x03abcEOF"[..]),
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x02xyEOF"[..]),
+        ];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.as_ref(), b"PATCH\x00\x00\x02\x00\x04abxyEOF");
+        check(&patches, &[b"0123456789", b"", b"01"]);
+    }
+
+    #[test]
+    fn test_merge_real() {
+        let patches = [flips::IpsPatch::new(PATCH_1TO2), flips::IpsPatch::new(PATCH_2TO1)];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.apply(DATA1).unwrap_err(), flips::Error::ToOutput);
+        let merged = flips::IpsPatch::merge(&patches[..1]).unwrap();
+        assert_eq!(merged.apply(DATA1).unwrap().as_ref(), DATA2);
+    }
+
+    #[test]
+    fn test_merge_rle() {
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x00\x00\x08zEOF"[..]),
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x03\x00\x02ab\x00\x00\x08\x00\x00\x00\x04cEOF"[..]),
+        ];
+        check(&patches, &[b"0123456789", b"", b"0123456789abcdef"]);
+    }
+
+    #[test]
+    fn test_merge_truncate() {
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02abEOF\x00\x00\x06"[..]),
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x02\x00\x01xEOF\x00\x00\x04"[..]),
+        ];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.as_ref(), b"PATCH\x00\x00\x01\x00\x02axEOF\x00\x00\x04");
+        check(&patches, &[b"0123456789", b"012"]);
+    }
+
+    #[test]
+    fn test_merge_zero_fill() {
+        // bytes removed by the truncation are zeroed when the output grows again
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x01aEOF\x00\x00\x03"[..]),
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x06\x00\x02xyEOF"[..]),
+        ];
+        check(&patches, &[b"0123456789", b"0123"]);
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.apply(&b"0123456789"[..]).unwrap().as_ref(), b"0a2\0\0\0xy");
+    }
+
+    #[test]
+    fn test_merge_ips32() {
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x01\x00\x02abEOF"[..]),
+            flips::IpsPatch::new(&b"IPS32\x00\x00\x00\x02\x00\x01xEEOF"[..]),
+        ];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.format().unwrap(), flips::IpsFormat::Ips32);
+        assert_eq!(merged.as_ref(), b"IPS32\x00\x00\x00\x01\x00\x02axEEOF");
+        check(&patches, &[b"0123456789"]);
+    }
+
+    #[test]
+    fn test_merge_empty() {
+        let merged = flips::IpsPatch::merge(&[] as &[flips::IpsPatch<&[u8]>]).unwrap();
+        assert_eq!(merged.as_ref(), b"PATCHEOF");
+    }
+
+    #[test]
+    fn test_merge_not_this() {
+        let patches = [flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x02abEOF\x00\x00\x03"[..])];
+        assert_eq!(flips::IpsPatch::merge(&patches).unwrap_err(), flips::Error::NotThis);
+    }
+
+    #[test]
+    fn test_merge_source_dependent() {
+        // the output keeps its source length unless the source is shorter than 8 bytes
+        let patches = [
+            flips::IpsPatch::new(&b"PATCH\x00\x00\x06\x00\x02abEOF"[..]),
+            flips::IpsPatch::new(&b"PATCHEOF\x00\x00\x04"[..]),
+        ];
+        assert_eq!(flips::IpsPatch::merge(&patches).unwrap_err(), flips::Error::Invalid);
+    }
+
+    #[test]
+    fn test_merge_eof_offset() {
+        // the zero-fill after the truncation crosses the footer marker offset
+        let patches = [
+            flips::IpsPatch::new(&b"PATCHEOF\x45\x4F\x40"[..]),
+            flips::IpsPatch::new(&b"PATCH\x45\x4F\x45\x00\x00\x00\x08zEOF"[..]),
+        ];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        for record in merged.records().unwrap() {
+            assert_ne!(record.unwrap().offset(), Some(0x454F46));
+        }
+        check(&patches, &[&vec![1; 0x454F60], b"0123"]);
+    }
+
+    #[test]
+    fn test_merge_eof_offset_unknown() {
+        // the zero-fill would start at the footer marker offset, after a
+        // byte only known from the source
+        let patches = [
+            flips::IpsPatch::new(&b"PATCHEOF\x45\x4F\x46"[..]),
+            flips::IpsPatch::new(&b"PATCH\x45\x4F\x50\x00\x02xyEOF"[..]),
+        ];
+        assert_eq!(flips::IpsPatch::merge(&patches).unwrap_err(), flips::Error::Invalid);
+    }
+
+    #[test]
+    fn test_merge_large() {
+        let patches = [flips::IpsPatch::new(&b"IPS32\x01\x00\x00\x00\x00\x01aEEOF"[..])];
+        assert_eq!(flips::IpsPatch::merge(&patches).unwrap().format().unwrap(), flips::IpsFormat::Ips32);
+        let patches = [flips::IpsPatch::new(&b"IPS32EEOF\x01\x00\x00\x00"[..])];
+        let merged = flips::IpsPatch::merge(&patches).unwrap();
+        assert_eq!(merged.as_ref(), b"IPS32EEOF\x01\x00\x00\x00");
+    }
+}
+
+#[cfg(feature = "std")]
+mod optimize {
+    use super::*;
+
+    fn check(patch: &[u8], sources: &[&[u8]]) -> Vec<u8> {
+        let patch = flips::IpsPatch::new(patch);
+        let optimized = patch.optimize().unwrap();
+        assert!(optimized.as_ref().len() <= patch.as_ref().len());
+        for source in sources {
+            let expected = patch.apply(source).map(|output| output.as_ref().to_vec());
+            let output = optimized.apply(source).map(|output| output.as_ref().to_vec());
+            assert_eq!(output, expected);
+        }
+        optimized.as_ref().to_vec()
+    }
+
+    #[test]
+    fn test_optimize_real() {
+        let optimized = check(PATCH_1TO2, &[DATA1, DATA2]);
+        assert_eq!(flips::IpsPatch::new(&optimized).apply(DATA1).unwrap().as_ref(), DATA2);
+        check(PATCH_2TO1, &[DATA1, DATA2]);
+    }
+
+    #[test]
+    fn test_optimize_adjacent() {
+        let mut patch = b"PATCH".to_vec();
+        for (i, byte) in b"abcdefgh".iter().enumerate() {
+            patch.extend_from_slice(&[0, 0, i as u8 + 2, 0, 1, *byte]);
+        }
+        patch.extend_from_slice(b"EOF");
+        let optimized = check(&patch, &[b"0123456789", b""]);
+        assert_eq!(optimized, b"PATCH\x00\x00\x02\x00\x08abcdefghEOF");
+    }
+
+    #[test]
+    fn test_optimize_overwritten() {
+        let patch = b"PATCH\x00\x00\x01\x00\x04abcd\x00\x00\x02\x00\x00\x00\x06zEOF";
+        let optimized = check(patch, &[b"0123456789"]);
+        assert_eq!(optimized, b"PATCH\x00\x00\x01\x00\x07azzzzzzEOF");
+    }
+
+    #[test]
+    fn test_optimize_rle() {
+        let mut patch = b"PATCH\x00\x00\x01\x00\x22ab".to_vec();
+        patch.extend_from_slice(&[b'z'; 32]);
+        patch.extend_from_slice(b"EOF");
+        let optimized = check(&patch, &[b"0123456789"]);
+        let optimized = flips::IpsPatch::new(optimized);
+        let records = optimized.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                flips::IpsRecord::Data { offset: 1, data: b"ab" },
+                flips::IpsRecord::Rle { offset: 3, len: 32, byte: b'z' },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optimize_truncate() {
+        let patch = b"PATCH\x00\x00\x01\x00\x01a\x00\x00\x02\x00\x01bEOF\x00\x00\x04";
+        let optimized = check(patch, &[b"0123456789", b"01"]);
+        assert_eq!(optimized, b"PATCH\x00\x00\x01\x00\x02abEOF\x00\x00\x04");
+    }
+
+    #[test]
+    fn test_optimize_ips32() {
+        let patch = b"IPS32\x00\x00\x00\x01\x00\x01a\x00\x00\x00\x02\x00\x01bEEOF";
+        let optimized = check(patch, &[b"0123456789"]);
+        assert_eq!(optimized, b"IPS32\x00\x00\x00\x01\x00\x02abEEOF");
+    }
+
+    #[test]
+    fn test_optimize_eof_offset() {
+        // the run starting at `0x454F46` cannot be written at its offset
+        let mut patch = b"PATCH\x45\x4F\x40\x00\x05abcde\x45\x4F\x45\x00\x1Ff".to_vec();
+        patch.extend_from_slice(&[b'z'; 30]);
+        patch.extend_from_slice(b"EOF");
+        let source = vec![b'0'; 0x454F80];
+        let optimized = check(&patch, &[&source]);
+        let optimized = flips::IpsPatch::new(optimized);
+        let records = optimized.records().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                flips::IpsRecord::Data { offset: 0x454F40, data: b"abcdefz" },
+                flips::IpsRecord::Rle { offset: 0x454F47, len: 29, byte: b'z' },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optimize_eof_offset_overwritten() {
+        // the bytes left at `0x454F46` by an overwritten record are written
+        // again with the byte before them
+        let patch = b"PATCH\x45\x4F\x45\x00\x03abc\x45\x4F\x45\x00\x01xEOF";
+        let source = vec![b'0'; 0x454F50];
+        let optimized = check(patch, &[&source, b"0123"]);
+        assert_eq!(optimized, b"PATCH\x45\x4F\x45\x00\x03xbcEOF");
+    }
+
+    #[test]
+    fn test_optimize_past_max_offset() {
+        // records merged near the end of the addressable range must still
+        // start before it, even if they write past it
+        let data = (0..0xFFFFu32).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut patch = b"PATCH\xFF\xFF\x00\xFF\xFF".to_vec();
+        patch.extend_from_slice(&data);
+        patch.extend_from_slice(b"\xFF\xFF\xFF\xFF\xFF");
+        patch.extend_from_slice(&data);
+        patch.extend_from_slice(b"EOF");
+        check(&patch, &[b""]);
+    }
+
+    #[test]
+    fn test_optimize_not_this() {
+        let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x02abEOF\x00\x00\x03"[..]);
+        assert_eq!(patch.optimize().unwrap_err(), flips::Error::NotThis);
+        let patch = flips::IpsPatch::new(&b"PATCH\x00\x00\x04\x00\x01a\x00\x00\x01\x00\x01bEOF"[..]);
+        assert_eq!(patch.optimize().unwrap_err(), flips::Error::Scrambled);
+    }
+}